@@ -0,0 +1,131 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Lookup benchmarks, run with `cargo bench`.
+
+use bp3d_env::Environment;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// An allocator counting the allocations of the whole process.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// The number of lookups made by each thread.
+const ITERATIONS: u32 = 1_000_000;
+
+/// Runs f ITERATIONS times on each of threads threads and returns the total time.
+fn run(threads: usize, f: impl Fn() + Sync) -> Duration {
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    f();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+/// Prints the time per lookup of a benchmark.
+fn report(name: &str, threads: usize, elapsed: Duration) {
+    let per_lookup = elapsed.as_nanos() as f64 / ITERATIONS as f64;
+    println!("{name:<32} {threads:>2} thread(s): {per_lookup:>8.1} ns/lookup per thread");
+}
+
+/// Runs f ITERATIONS times on the current thread and returns the number of allocations per call.
+fn allocations(f: impl Fn()) -> f64 {
+    let start = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - start) as f64 / ITERATIONS as f64
+}
+
+/// Reads a warm key from an increasing number of threads.
+///
+/// Cache hits only take a read lock, so on a machine with at least as many cores as threads the
+/// time per lookup should stay roughly constant as threads are added instead of growing linearly.
+fn concurrent_warm_reads() {
+    let env = Environment::new();
+    env.set_override("BENCH_WARM", "value");
+    for threads in [1, 2, 4, 8] {
+        let elapsed = run(threads, || {
+            black_box(env.get_os(black_box("BENCH_WARM")));
+        });
+        report("get_os (warm)", threads, elapsed);
+    }
+}
+
+/// Compares reading a warm key with and without cloning its value.
+fn borrowed_warm_reads() {
+    let env = Environment::new();
+    env.set_override("BENCH_BORROWED", "a value long enough to require an allocation");
+    let elapsed = run(1, || {
+        black_box(env.get_os(black_box("BENCH_BORROWED")));
+    });
+    report("get_os (warm, cloned)", 1, elapsed);
+    let elapsed = run(1, || {
+        black_box(env.with_value(black_box("BENCH_BORROWED"), |v| v.map(|v| v.len())));
+    });
+    report("with_value (warm, borrowed)", 1, elapsed);
+    let cloned = allocations(|| {
+        black_box(env.get_os(black_box("BENCH_BORROWED")));
+    });
+    let borrowed = allocations(|| {
+        black_box(env.with_value(black_box("BENCH_BORROWED"), |v| v.map(|v| v.len())));
+    });
+    println!("get_os (warm, cloned)            {cloned:>8.2} allocation(s)/lookup");
+    println!("with_value (warm, borrowed)      {borrowed:>8.2} allocation(s)/lookup");
+    // Cache hits look entries up by borrowed name, so only get_os allocates, to clone the value.
+    assert_eq!(borrowed, 0.0, "with_value allocated on a cache hit");
+}
+
+fn main() {
+    concurrent_warm_reads();
+    borrowed_warm_reads();
+}
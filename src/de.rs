@@ -0,0 +1,180 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Deserialization of structures from environment variables.
+
+use crate::parser::parse_bool;
+use crate::Environment;
+use serde::de::value::MapDeserializer;
+use serde::de::{DeserializeOwned, IntoDeserializer, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// An error returned when deserializing a structure from environment variables.
+#[derive(Debug)]
+pub enum EnvError {
+    /// A variable could not be parsed into the type of its field.
+    InvalidValue {
+        /// The name of the field which failed to parse.
+        field: String,
+
+        /// The value of the variable.
+        value: String,
+    },
+
+    /// Any other deserialization error, such as a missing field.
+    Message(String),
+}
+
+impl Display for EnvError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvError::InvalidValue { field, value } => {
+                write!(f, "invalid value {:?} for field `{}`", value, field)
+            }
+            EnvError::Message(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl Error for EnvError {}
+
+impl serde::de::Error for EnvError {
+    fn custom<T: Display>(msg: T) -> Self {
+        EnvError::Message(msg.to_string())
+    }
+}
+
+/// A deserializer for the value of a single variable.
+struct Value {
+    field: String,
+    value: String,
+}
+
+impl Value {
+    fn invalid(&self) -> EnvError {
+        EnvError::InvalidValue {
+            field: self.field.clone(),
+            value: self.value.clone(),
+        }
+    }
+
+    fn parse<T: FromStr>(&self) -> Result<T, EnvError> {
+        self.value.parse().map_err(|_| self.invalid())
+    }
+}
+
+impl<'de> IntoDeserializer<'de, EnvError> for Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parse {
+    ($($name: ident => $visit: ident),*) => {
+        $(
+            fn $name<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EnvError> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = EnvError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EnvError> {
+        visitor.visit_string(self.value)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EnvError> {
+        match parse_bool(&self.value) {
+            Some(v) => visitor.visit_bool(v),
+            None => Err(self.invalid()),
+        }
+    }
+
+    deserialize_parse! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EnvError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V
+    ) -> Result<V::Value, EnvError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V
+    ) -> Result<V::Value, EnvError> {
+        visitor.visit_enum(self.value.into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+impl Environment {
+    /// Deserializes a structure from all variables starting with the given prefix.
+    ///
+    /// See [from_env](crate::from_env) for details.
+    pub fn from_env<T: DeserializeOwned>(&self, prefix: &str) -> Result<T, EnvError> {
+        let entries = self.snapshot().into_iter().filter_map(|(k, v)| {
+            let key = k.into_string().ok()?;
+            let field = key.strip_prefix(prefix)?.to_lowercase();
+            let value = v.into_string().ok()?;
+            Some((field.clone(), Value { field, value }))
+        });
+        T::deserialize(MapDeserializer::new(entries))
+    }
+}
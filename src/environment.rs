@@ -0,0 +1,2450 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::error::AddPathError;
+use crate::file::{os_str_from_bytes, scan_file, scan_file_last, Encoding, Source};
+use crate::key::{keys_equal, Key, KeyRef};
+use crate::parser;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+type Cache = HashMap<Key, Entry>;
+
+/// Where a cached value comes from.
+#[derive(Clone, Copy)]
+enum Origin {
+    /// A programmatic override.
+    Override,
+
+    /// The process environment.
+    Process,
+
+    /// The override source at the given index in the source list.
+    Source(usize),
+
+    /// The variable was not found in any source at the given time.
+    Missing(Instant),
+}
+
+/// A cache entry.
+#[derive(Clone)]
+struct Entry {
+    value: Option<OsString>,
+    origin: Origin,
+}
+
+impl Entry {
+    /// Creates a programmatic override entry.
+    fn new(value: Option<OsString>) -> Entry {
+        Entry {
+            value,
+            origin: Origin::Override,
+        }
+    }
+
+    /// Returns true if this entry is a negative entry older than ttl.
+    fn is_expired(&self, ttl: Option<Duration>) -> bool {
+        match (self.origin, ttl) {
+            (Origin::Missing(time), Some(ttl)) => time.elapsed() >= ttl,
+            _ => false,
+        }
+    }
+}
+
+/// Which definition of a key is used when it's defined several times in the same override file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// The first definition in the file is used, this is the default.
+    #[default]
+    FirstWins,
+
+    /// The last definition in the file is used, like when sourcing the file in a shell.
+    LastWins,
+}
+
+/// The settings of an [Environment].
+#[derive(Clone, Copy)]
+pub(crate) struct Config {
+    pub interpolation: bool,
+    pub percent_interpolation: bool,
+    pub file_precedence: bool,
+    pub delimiter: u8,
+    pub negative_ttl: Option<Duration>,
+    pub keep_empty: bool,
+    pub max_file_size: Option<u64>,
+    pub max_cache_entries: Option<usize>,
+    pub duplicates: DuplicatePolicy,
+    pub mtime_revalidation: bool,
+}
+
+impl Config {
+    /// Returns true if a value should be returned by the getters rather than treated as unset.
+    fn accepts(&self, value: &OsStr) -> bool {
+        self.keep_empty || !value.is_empty()
+    }
+
+    /// Returns true if values read from override sources may reference other variables.
+    fn interpolates(&self) -> bool {
+        self.interpolation || self.percent_interpolation
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            interpolation: false,
+            percent_interpolation: false,
+            file_precedence: false,
+            delimiter: b'=',
+            negative_ttl: None,
+            keep_empty: false,
+            max_file_size: None,
+            max_cache_entries: None,
+            duplicates: DuplicatePolicy::FirstWins,
+            mtime_revalidation: false,
+        }
+    }
+}
+
+/// Reads a variable from the process environment.
+#[cfg(not(feature = "no-process-env"))]
+fn process_var(name: &OsStr) -> Option<OsString> {
+    std::env::var_os(name)
+}
+
+/// Reads a variable from the process environment, which is disabled by the `no-process-env`
+/// feature.
+#[cfg(feature = "no-process-env")]
+fn process_var(_: &OsStr) -> Option<OsString> {
+    None
+}
+
+/// Lists the names of all variables in the process environment.
+#[cfg(not(feature = "no-process-env"))]
+fn process_keys() -> Vec<OsString> {
+    std::env::vars_os().map(|(k, _)| k).collect()
+}
+
+/// Lists the names of all variables in the process environment, which is disabled by the
+/// `no-process-env` feature.
+#[cfg(feature = "no-process-env")]
+fn process_keys() -> Vec<OsString> {
+    Vec::new()
+}
+
+/// The counters of an [Environment], see [CacheStats].
+#[derive(Default)]
+struct Stats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    source_reads: AtomicU64,
+}
+
+/// Statistics about the cache of an [Environment], as returned by
+/// [cache_stats](Environment::cache_stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// The number of lookups answered by the cache.
+    pub hits: u64,
+
+    /// The number of lookups which had to resolve the variable from the sources.
+    pub misses: u64,
+
+    /// The number of times an override source was read.
+    pub source_reads: u64,
+
+    /// The number of entries currently in the cache, including programmatic overrides.
+    pub entries: usize,
+
+    /// The number of entries currently in the cache for variables which were not found.
+    pub negative_entries: usize,
+}
+
+/// Resolves variables which are not yet in the cache.
+struct Resolver<'a> {
+    cache: &'a mut Cache,
+    stats: &'a Stats,
+    sources: &'a [Source],
+    config: Config,
+    /// The variables being resolved, used to detect interpolation cycles.
+    visited: Vec<OsString>,
+    /// The lowest index in visited of a variable referenced by an interpolation cycle found
+    /// while resolving the current variable.
+    cycle: Option<usize>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(env: &'a Environment, cache: &'a mut Cache, sources: &'a [Source]) -> Resolver<'a> {
+        Resolver {
+            cache,
+            stats: &env.stats,
+            sources,
+            config: env.config(),
+            visited: Vec::new(),
+            cycle: None,
+        }
+    }
+
+    /// Expands `${NAME}` references, and `%NAME%` references when percent interpolation is
+    /// enabled, found in value.
+    ///
+    /// Unresolved `${NAME}` references expand to an empty string while unresolved `%NAME%`
+    /// references are left unexpanded; references which would recurse into a variable currently
+    /// being resolved are left unexpanded. `$$` expands to a literal `$` and `%%` to a literal `%`.
+    fn interpolate(&mut self, value: &[u8]) -> Option<OsString> {
+        let mut res = OsString::new();
+        let mut literal = Vec::new();
+        let mut i = 0;
+        while i < value.len() {
+            let (escape, open, close, keep) = match value[i] {
+                b'$' if self.config.interpolation => (b'$', &b"${"[..], b'}', false),
+                b'%' if self.config.percent_interpolation => (b'%', &b"%"[..], b'%', true),
+                _ => {
+                    literal.push(value[i]);
+                    i += 1;
+                    continue;
+                }
+            };
+            if value.get(i + 1) == Some(&escape) {
+                literal.push(escape);
+                i += 2;
+                continue;
+            }
+            let start = i + open.len();
+            if value[i..].starts_with(open) {
+                if let Some(len) = value[start..].iter().position(|v| *v == close) {
+                    let token = &value[i..start + len + 1];
+                    let name = os_str_from_bytes(&value[start..start + len])?;
+                    if let Some(index) = self.visited.iter().position(|v| keys_equal(v, name)) {
+                        // Cycle detected: leave the token unexpanded.
+                        self.cycle = Some(self.cycle.map_or(index, |v| v.min(index)));
+                        literal.extend_from_slice(token);
+                    } else {
+                        res.push(os_str_from_bytes(&literal)?);
+                        literal.clear();
+                        match self.resolve(name) {
+                            Some(v) => res.push(v),
+                            None if keep => literal.extend_from_slice(token),
+                            None => (),
+                        }
+                    }
+                    i += token.len();
+                    continue;
+                }
+            }
+            literal.push(value[i]);
+            i += 1;
+        }
+        res.push(os_str_from_bytes(&literal)?);
+        Some(res)
+    }
+
+    /// Parses the raw value of a variable read from an override source.
+    ///
+    /// Returns None if the value can't be represented on this platform, which only happens on
+    /// Windows for values which are not valid UTF-8.
+    fn parse_value(&mut self, name: &OsStr, raw: &[u8]) -> Option<OsString> {
+        let value = parser::parse_value(raw);
+        let res = if self.config.interpolates() {
+            self.visited.push(name.into());
+            let value = self.interpolate(&value);
+            self.visited.pop();
+            value
+        } else {
+            os_str_from_bytes(&value).map(OsString::from)
+        };
+        if res.is_none() {
+            warn!(?name, "skipping override entry which is not valid UTF-8");
+        }
+        res
+    }
+
+    /// Attempts to read a variable from the override sources matching filter.
+    ///
+    /// Returns Some(None) if the variable is unset by an `@unset` directive.
+    fn resolve_files(
+        &mut self,
+        name: &OsStr,
+        filter: fn(&Source) -> bool
+    ) -> Option<Option<Entry>> {
+        let config = self.config;
+        for (i, v) in self.sources.iter().enumerate() {
+            if !filter(v) {
+                continue;
+            }
+            trace!(source = ?v, "reading override source");
+            self.stats.source_reads.fetch_add(1, Ordering::Relaxed);
+            // Some(None) means the variable is unset by an `@unset` directive.
+            let value = match self.config.duplicates {
+                DuplicatePolicy::FirstWins => scan_file(v, &config, |key, value| {
+                    if !keys_equal(os_str_from_bytes(key)?, name) {
+                        return None;
+                    }
+                    match value {
+                        Some(value) => self.parse_value(name, value).map(Some),
+                        None => Some(None),
+                    }
+                }),
+                DuplicatePolicy::LastWins => scan_file_last(v, &config, |key| {
+                    os_str_from_bytes(key).map(|k| keys_equal(k, name)) == Some(true)
+                })
+                .and_then(|raw| match raw {
+                    Some(raw) => self.parse_value(name, &raw).map(Some),
+                    None => Some(None),
+                }),
+            };
+            match value {
+                Some(Some(value)) => {
+                    debug!(?name, source = ?v, "resolved variable from override source");
+                    return Some(Some(Entry {
+                        value: Some(value),
+                        origin: Origin::Source(i),
+                    }));
+                }
+                Some(None) => {
+                    // The remaining sources are not read.
+                    debug!(?name, source = ?v, "variable unset by override source");
+                    return Some(None);
+                }
+                None => (),
+            }
+        }
+        None
+    }
+
+    /// Attempts to read a variable from the process environment.
+    fn resolve_process(&self, name: &OsStr) -> Option<Entry> {
+        let value = process_var(name)?;
+        debug!(?name, "resolved variable from process environment");
+        Some(Entry {
+            value: Some(value),
+            origin: Origin::Process,
+        })
+    }
+
+    /// Resolves a variable which may or may not be in the cache.
+    fn resolve(&mut self, name: &OsStr) -> Option<OsString> {
+        self.resolve_entry(name).value
+    }
+
+    /// Resolves a variable which may or may not be in the cache, returning its origin.
+    fn resolve_entry(&mut self, name: &OsStr) -> Entry {
+        {
+            // Attempt to pull from the cache. An entry pointing to a source which no longer exists
+            // is treated as a miss.
+            if let Some(entry) = self.cache.get(KeyRef::new(name)) {
+                let stale = matches!(entry.origin, Origin::Source(i) if i >= self.sources.len());
+                if !stale && !entry.is_expired(self.config.negative_ttl) {
+                    self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                    return entry.clone();
+                }
+            }
+        }
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+        // The cycle flag is scoped to the resolution of this variable, which is pushed to visited
+        // at index depth while its value is interpolated.
+        let depth = self.visited.len();
+        let outer = self.cycle.take();
+        // Value is not in cache, try pulling from environment variables and the override file
+        // list in the configured order. `.env` files never take precedence over the process
+        // environment.
+        let entry = if self.config.file_precedence {
+            match self.resolve_files(name, |v| !v.is_dotenv()) {
+                Some(entry) => entry.or_else(|| self.resolve_process(name)),
+                None => self.resolve_process(name).or_else(|| {
+                    self.resolve_files(name, Source::is_dotenv).flatten()
+                }),
+            }
+        } else {
+            self.resolve_process(name).or_else(|| self.resolve_files(name, |_| true).flatten())
+        };
+        // If everything failed, a None is placed in the cache to assume the variable does not
+        // exist. A value which is part of an interpolation cycle is not cached as it would depend
+        // on the order in which variables are resolved.
+        let entry = entry.unwrap_or_else(|| {
+            debug!(?name, "variable not found");
+            Entry {
+                value: None,
+                origin: Origin::Missing(Instant::now()),
+            }
+        });
+        let cycle = std::mem::replace(&mut self.cycle, outer);
+        if let Some(index) = cycle {
+            // The cycle only affects the variables being resolved if it references one of them.
+            if index < depth {
+                self.cycle = Some(self.cycle.map_or(index, |v| v.min(index)));
+            }
+        } else {
+            if let Some(max) = self.config.max_cache_entries {
+                if self.cache.len() >= max {
+                    // The cache is full: evict every resolved entry. Programmatic overrides are
+                    // kept as they can't be resolved again.
+                    debug!(len = self.cache.len(), max, "evicting cache entries");
+                    self.cache.retain(|_, v| matches!(v.origin, Origin::Override));
+                }
+            }
+            self.cache.insert(name.into(), entry.clone());
+        }
+        entry
+    }
+}
+
+/// The source of a resolved value, as returned by [get_with_source](Environment::get_with_source).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueSource {
+    /// The value comes from the process environment.
+    ProcessEnv,
+
+    /// The value comes from the override file at the given path.
+    File(PathBuf),
+
+    /// The value comes from override entries loaded in memory.
+    Memory,
+
+    /// The value is a programmatic override, either global or thread-local.
+    Programmatic,
+}
+
+/// Lists the keys defined in a list of override sources, including duplicates.
+fn source_keys(sources: &[Source], config: &Config) -> Vec<OsString> {
+    let mut keys = Vec::new();
+    for source in sources {
+        scan_file(source, config, |key, value| {
+            if value.is_some() {
+                keys.extend(os_str_from_bytes(key).map(OsString::from));
+            }
+            None::<()>
+        });
+    }
+    keys
+}
+
+/// Returns the position of a registered override path, ignoring the defaults file.
+fn find_path(sources: &[Source], path: &Path) -> Option<usize> {
+    sources.iter().position(|v| !v.is_defaults() && v.path() == Some(path))
+}
+
+/// Appends a source to the override source list, keeping the defaults file last.
+fn push_source(sources: &mut Vec<Source>, source: Source) {
+    let len = match sources.last() {
+        Some(v) if v.is_defaults() => sources.len() - 1,
+        _ => sources.len(),
+    };
+    sources.insert(len, source);
+}
+
+/// Checks that a path points to a file which can be used as an override file.
+fn check_file(path: &Path) -> Result<(), AddPathError> {
+    let metadata = std::fs::symlink_metadata(path).map_err(AddPathError::Io)?;
+    if !metadata.is_file() {
+        return Err(AddPathError::NotFile);
+    }
+    Ok(())
+}
+
+/// Checks that a path does not point to a directory or a symlink.
+///
+/// Unlike [check_file], this accepts paths which don't exist or whose metadata can't be read, like
+/// the panicking `add_override_path` functions always did.
+fn check_not_dir(path: &Path) -> Result<(), AddPathError> {
+    if path.is_dir() || path.is_symlink() {
+        return Err(AddPathError::NotFile);
+    }
+    Ok(())
+}
+
+/// A guard which restores the previous override of a variable when dropped.
+///
+/// This is returned by [scoped_override](Environment::scoped_override).
+pub struct OverrideGuard<'a> {
+    env: &'a Environment,
+    key: Key,
+    previous: Option<Entry>,
+}
+
+impl Drop for OverrideGuard<'_> {
+    fn drop(&mut self) {
+        let mut lock = self.env.cache.write().unwrap();
+        match self.previous.take() {
+            Some(v) => lock.insert(std::mem::take(&mut self.key), v),
+            None => lock.remove(&self.key),
+        };
+    }
+}
+
+/// A unique identifier of an [Environment].
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Id(usize);
+
+impl Default for Id {
+    fn default() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        Id(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+thread_local! {
+    /// The thread-local overrides of the current thread, for each environment.
+    static THREAD_OVERRIDES: RefCell<HashMap<Id, HashMap<Key, OsString>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// An isolated environment with its own override path list and value cache.
+///
+/// The free functions of this crate operate on a global environment; an [Environment] can be
+/// used instead to run isolated configurations in the same process, for example in parallel
+/// tests or plugin sandboxes.
+#[derive(Default)]
+pub struct Environment {
+    sources: Mutex<Vec<Source>>,
+    cache: RwLock<Cache>,
+    config: RwLock<Config>,
+    prefix: RwLock<OsString>,
+    id: Id,
+    callbacks: Mutex<Vec<Box<dyn Fn() + Send>>>,
+    frozen: AtomicBool,
+    stats: Stats,
+    mtimes: Mutex<HashMap<PathBuf, Option<SystemTime>>>,
+}
+
+impl Environment {
+    /// Creates a new empty environment.
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    /// Returns a copy of the current settings.
+    pub(crate) fn config(&self) -> Config {
+        *self.config.read().unwrap()
+    }
+
+    /// Changes the settings and invalidates the cache.
+    fn configure(&self, f: impl FnOnce(&mut Config)) {
+        {
+            let mut lock = self.cache.write().unwrap();
+            f(&mut self.config.write().unwrap());
+            lock.clear();
+        }
+        self.changed();
+    }
+
+    /// Invalidates the cache, then releases the source list and calls the change callbacks.
+    fn invalidate(&self, sources: MutexGuard<Vec<Source>>) {
+        self.cache.write().unwrap().clear();
+        drop(sources);
+        self.changed();
+    }
+
+    /// Calls the change callbacks.
+    ///
+    /// No other lock of this environment may be held when calling this function.
+    fn changed(&self) {
+        let callbacks = self.callbacks.lock().unwrap();
+        for f in callbacks.iter() {
+            f();
+        }
+    }
+
+    /// Registers a callback called whenever the cache is invalidated.
+    ///
+    /// See [on_change](crate::on_change) for details.
+    pub fn on_change(&self, callback: impl Fn() + Send + 'static) {
+        self.callbacks.lock().unwrap().push(Box::new(callback));
+    }
+
+    /// Freezes the override source list and the programmatic overrides of this environment.
+    ///
+    /// See [freeze](crate::freeze) for details.
+    pub fn freeze(&self) {
+        self.frozen.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if this environment is frozen.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Relaxed)
+    }
+
+    /// Panics if this environment is frozen.
+    fn check_frozen(&self) {
+        if self.is_frozen() {
+            panic!("Cannot modify a frozen environment");
+        }
+    }
+
+    /// Attempts to add a new override path.
+    ///
+    /// Returns true if the path was added and false if it was already added, in which case
+    /// nothing happens. When a new path is added, the cache is invalidated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [AddPathError] if the path does not point to a file or if its metadata could
+    /// not be read (for example when the file does not exist).
+    pub fn try_add_override_path<P: AsRef<Path>>(&self, path: P) -> Result<bool, AddPathError> {
+        let path = path.as_ref();
+        self.try_add_override_path_with_encoding(path, Encoding::Utf8)
+    }
+
+    /// Attempts to add a new override path decoded with the given encoding.
+    ///
+    /// Returns true if the path was added. If the path is already added, only its encoding is
+    /// updated and false is returned. The cache is invalidated when a new path is added or when
+    /// the encoding changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [AddPathError] if the path does not point to a file or if its metadata could
+    /// not be read (for example when the file does not exist).
+    pub fn try_add_override_path_with_encoding<P: AsRef<Path>>(
+        &self,
+        path: P,
+        encoding: Encoding
+    ) -> Result<bool, AddPathError> {
+        let path = path.as_ref();
+        if self.is_frozen() {
+            return Err(AddPathError::Frozen);
+        }
+        check_file(path)?;
+        Ok(self.push_path(path, encoding))
+    }
+
+    /// Adds a new override path which may not exist yet, reporting duplicate registrations.
+    ///
+    /// See [add_override_path_deferred](crate::add_override_path_deferred) for details.
+    ///
+    /// # Panics
+    ///
+    /// The function panics if the path points to a directory or a symlink, or if the environment
+    /// is frozen (see [freeze](Self::freeze)).
+    pub fn add_override_path_deferred<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        self.check_frozen();
+        if let Err(e) = check_not_dir(path) {
+            panic!("Cannot add environment override path: {}", e);
+        }
+        self.push_path(path, Encoding::Utf8)
+    }
+
+    /// Adds a new TOML override file.
+    ///
+    /// See [add_toml_override](crate::add_toml_override) for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [AddPathError] if the path does not point to a file or if its metadata could
+    /// not be read (for example when the file does not exist).
+    #[cfg(feature = "toml")]
+    pub fn add_toml_override<P: AsRef<Path>>(&self, path: P) -> Result<bool, AddPathError> {
+        let path = path.as_ref();
+        if self.is_frozen() {
+            return Err(AddPathError::Frozen);
+        }
+        check_file(path)?;
+        let mut lock = self.sources.lock().unwrap();
+        if find_path(&lock, path).is_some() {
+            return Ok(false);
+        }
+        push_source(&mut lock, Source::Toml(path.into()));
+        self.invalidate(lock);
+        Ok(true)
+    }
+
+    /// Adds a new `.env` file.
+    ///
+    /// See [add_dotenv](crate::add_dotenv) for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [AddPathError] if the path does not point to a file or if its metadata could
+    /// not be read (for example when the file does not exist).
+    pub fn add_dotenv<P: AsRef<Path>>(&self, path: P) -> Result<bool, AddPathError> {
+        let path = path.as_ref();
+        if self.is_frozen() {
+            return Err(AddPathError::Frozen);
+        }
+        check_file(path)?;
+        let mut lock = self.sources.lock().unwrap();
+        if find_path(&lock, path).is_some() {
+            return Ok(false);
+        }
+        push_source(&mut lock, Source::Dotenv(path.into()));
+        self.invalidate(lock);
+        Ok(true)
+    }
+
+    /// Sets the defaults file.
+    ///
+    /// See [set_defaults_path](crate::set_defaults_path) for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [AddPathError] if the path does not point to a file or if its metadata could
+    /// not be read (for example when the file does not exist).
+    pub fn set_defaults_path<P: AsRef<Path>>(&self, path: P) -> Result<(), AddPathError> {
+        let path = path.as_ref();
+        if self.is_frozen() {
+            return Err(AddPathError::Frozen);
+        }
+        check_file(path)?;
+        let mut lock = self.sources.lock().unwrap();
+        match lock.last_mut() {
+            Some(Source::Defaults(v)) if v == path => return Ok(()),
+            Some(Source::Defaults(v)) => *v = path.into(),
+            _ => lock.push(Source::Defaults(path.into())),
+        }
+        self.invalidate(lock);
+        Ok(())
+    }
+
+    /// Returns the path of the defaults file, if any.
+    pub fn defaults_path(&self) -> Option<PathBuf> {
+        let lock = self.sources.lock().unwrap();
+        match lock.last() {
+            Some(Source::Defaults(path)) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    /// Appends a path to the source list, or updates its encoding if it's already added.
+    ///
+    /// Returns true if the path was not already added.
+    fn push_path(&self, path: &Path, encoding: Encoding) -> bool {
+        let mut lock = self.sources.lock().unwrap();
+        let added = match find_path(&lock, path).map(|i| &mut lock[i]) {
+            Some(Source::File(_, v)) if *v != encoding => {
+                *v = encoding;
+                false
+            }
+            Some(_) => return false,
+            None => {
+                push_source(&mut lock, Source::File(path.into(), encoding));
+                true
+            }
+        };
+        self.invalidate(lock);
+        added
+    }
+
+    /// Adds a new override path.
+    ///
+    /// This is the panicking version of [try_add_override_path](Self::try_add_override_path),
+    /// except that a path which does not exist is accepted.
+    ///
+    /// # Panics
+    ///
+    /// The function panics if the path points to a directory or a symlink, or if the environment
+    /// is frozen (see [freeze](Self::freeze)).
+    pub fn add_override_path<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        self.add_override_path_with_encoding(path, Encoding::Utf8)
+    }
+
+    /// Adds a new override path decoded with the given encoding.
+    ///
+    /// This is the panicking version of
+    /// [try_add_override_path_with_encoding](Self::try_add_override_path_with_encoding), except
+    /// that a path which does not exist is accepted.
+    ///
+    /// # Panics
+    ///
+    /// The function panics if the path points to a directory or a symlink, or if the environment
+    /// is frozen (see [freeze](Self::freeze)).
+    pub fn add_override_path_with_encoding<P: AsRef<Path>>(&self, path: P, encoding: Encoding) {
+        let path = path.as_ref();
+        self.check_frozen();
+        if let Err(e) = check_not_dir(path) {
+            panic!("Cannot add environment override path: {}", e);
+        }
+        self.push_path(path, encoding);
+    }
+
+    /// Returns the encoding of a registered override path, or UTF-8 if the path is not registered.
+    pub(crate) fn encoding_of(&self, path: &Path) -> Encoding {
+        let lock = self.sources.lock().unwrap();
+        lock.iter()
+            .find_map(|v| match v {
+                Source::File(v, encoding) if v == path => Some(*encoding),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    /// Adds every `*.env` file of a directory as override paths.
+    ///
+    /// See [add_override_dir](crate::add_override_dir) for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory could not be read, in which case no path is added, or an
+    /// error of kind [PermissionDenied](std::io::ErrorKind::PermissionDenied) if the environment
+    /// is frozen (see [freeze](Self::freeze)).
+    pub fn add_override_dir<P: AsRef<Path>>(&self, dir: P) -> std::io::Result<usize> {
+        let dir = dir.as_ref();
+        if self.is_frozen() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "Cannot modify a frozen environment"
+            ));
+        }
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension() == Some(OsStr::new("env")) && entry.file_type()?.is_file() {
+                paths.push(path);
+            }
+        }
+        // All paths share the same parent so this sorts by file name.
+        paths.sort();
+        let mut lock = self.sources.lock().unwrap();
+        let len = lock.len();
+        for path in paths {
+            if find_path(&lock, &path).is_none() {
+                push_source(&mut lock, Source::File(path, Encoding::Utf8));
+            }
+        }
+        let count = lock.len() - len;
+        if count > 0 {
+            self.invalidate(lock);
+        }
+        Ok(count)
+    }
+
+    /// Adds a new override path with the highest priority.
+    ///
+    /// The path is inserted at the front of the override path list. If the path is already added,
+    /// it's moved to the front of the list. The cache is invalidated when the list changes.
+    ///
+    /// # Panics
+    ///
+    /// The function panics if the path points to a directory or a symlink, or if the environment
+    /// is frozen (see [freeze](Self::freeze)).
+    pub fn add_override_path_front<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        self.check_frozen();
+        if let Err(e) = check_not_dir(path) {
+            panic!("Cannot add environment override path: {}", e);
+        }
+        let mut lock = self.sources.lock().unwrap();
+        let index = find_path(&lock, path);
+        if index == Some(0) {
+            return;
+        }
+        // Moving a path to the front keeps its format and encoding.
+        let source = match index {
+            Some(i) => lock.remove(i),
+            None => Source::File(path.into(), Encoding::Utf8),
+        };
+        lock.insert(0, source);
+        self.invalidate(lock);
+    }
+
+    /// Removes an override path.
+    ///
+    /// Returns true if the path was previously registered and has been removed, false otherwise.
+    /// When a path is removed, the cache is invalidated.
+    pub fn remove_override_path<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref();
+        self.check_frozen();
+        let mut lock = self.sources.lock().unwrap();
+        let len = lock.len();
+        lock.retain(|v| v.is_defaults() || v.path() != Some(path));
+        if lock.len() == len {
+            return false;
+        }
+        self.invalidate(lock);
+        true
+    }
+
+    /// Invalidates the cached values which may be affected by a change to an override file.
+    ///
+    /// See [reload_path](crate::reload_path) for details.
+    pub fn reload_path<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        let sources = self.sources.lock().unwrap();
+        let index = match sources.iter().position(|v| v.path() == Some(path)) {
+            Some(v) => v,
+            None => return,
+        };
+        self.evict_from(index);
+        drop(sources);
+        self.changed();
+    }
+
+    /// Removes the cached values which may have been read from the source at index or from any
+    /// source after it.
+    ///
+    /// The source list must be locked by the caller.
+    fn evict_from(&self, index: usize) {
+        let config = self.config();
+        let mut cache = self.cache.write().unwrap();
+        cache.retain(|_, entry| match entry.origin {
+            Origin::Override => true,
+            Origin::Process => !config.file_precedence,
+            Origin::Source(i) => i < index && !config.interpolates(),
+            Origin::Missing(_) => false,
+        });
+    }
+
+    /// Invalidates the cached values read from override files whose modification time changed
+    /// since the last call, when mtime revalidation is enabled.
+    fn revalidate(&self) {
+        if !self.config().mtime_revalidation {
+            return;
+        }
+        let sources = self.sources.lock().unwrap();
+        let mut mtimes = self.mtimes.lock().unwrap();
+        let mut changed = None;
+        for (i, source) in sources.iter().enumerate() {
+            let path = match source.path() {
+                Some(v) => v,
+                None => continue,
+            };
+            // Files which don't exist (yet) are recorded without a modification time.
+            let mtime = std::fs::metadata(path).and_then(|v| v.modified()).ok();
+            let previous = mtimes.insert(path.into(), mtime);
+            if changed.is_none() && matches!(previous, Some(v) if v != mtime) {
+                debug!(?path, "override file modified");
+                changed = Some(i);
+            }
+        }
+        drop(mtimes);
+        if let Some(index) = changed {
+            self.evict_from(index);
+            drop(sources);
+            self.changed();
+        }
+    }
+
+    /// Returns a copy of the override path list, in priority order.
+    ///
+    /// In-memory override sources and the defaults file are not included.
+    pub fn override_paths(&self) -> Vec<PathBuf> {
+        let lock = self.sources.lock().unwrap();
+        lock.iter()
+            .filter(|v| !v.is_defaults())
+            .filter_map(|v| v.path().map(Path::to_path_buf))
+            .collect()
+    }
+
+    /// Returns the number of registered override paths.
+    ///
+    /// In-memory override sources and the defaults file are not counted.
+    pub fn override_path_count(&self) -> usize {
+        let lock = self.sources.lock().unwrap();
+        lock.iter().filter(|v| !v.is_defaults() && v.path().is_some()).count()
+    }
+
+    /// Adds override entries from a string, with the lowest priority.
+    ///
+    /// The string uses the same format as override files. The entries are appended to the
+    /// override source list and are consulted in registration order together with override
+    /// files, following the same precedence relative to the process environment (see
+    /// [set_file_precedence](Self::set_file_precedence)). Unlike programmatic overrides, these
+    /// entries survive [clear_cache](Self::clear_cache). The cache is invalidated.
+    pub fn add_override_str(&self, contents: &str) {
+        self.add_source(Source::Memory(contents.as_bytes().into()));
+    }
+
+    /// Adds override entries read from a reader, with the lowest priority.
+    ///
+    /// The reader is read to the end immediately; see [add_override_str](Self::add_override_str)
+    /// for details on how the entries are used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the reader could not be read, in which case no entries are added.
+    pub fn add_override_reader<R: Read>(&self, mut reader: R) -> std::io::Result<()> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.add_source(Source::Memory(data.into()));
+        Ok(())
+    }
+
+    /// Appends a source to the override source list and invalidates the cache.
+    fn add_source(&self, source: Source) {
+        self.check_frozen();
+        let mut lock = self.sources.lock().unwrap();
+        push_source(&mut lock, source);
+        self.invalidate(lock);
+    }
+
+    /// Clears the value cache, including programmatic overrides.
+    pub fn clear_cache(&self) {
+        self.cache.write().unwrap().clear();
+        self.changed();
+    }
+
+    /// Resets this environment to its initial state.
+    ///
+    /// See [reset](crate::reset) for details.
+    pub fn reset(&self) {
+        {
+            let mut sources = self.sources.lock().unwrap();
+            let mut cache = self.cache.write().unwrap();
+            let mut config = self.config.write().unwrap();
+            sources.clear();
+            cache.clear();
+            *config = Config::default();
+            self.mtimes.lock().unwrap().clear();
+            self.prefix.write().unwrap().clear();
+            self.frozen.store(false, Ordering::Relaxed);
+            self.stats.hits.store(0, Ordering::Relaxed);
+            self.stats.misses.store(0, Ordering::Relaxed);
+            self.stats.source_reads.store(0, Ordering::Relaxed);
+        }
+        self.clear_thread_overrides();
+        self.changed();
+    }
+
+    /// Overrides the value of a variable for the current thread only.
+    ///
+    /// See [set_thread_override](crate::set_thread_override) for details.
+    pub fn set_thread_override<K: AsRef<OsStr>, V: AsRef<OsStr>>(&self, key: K, value: V) {
+        THREAD_OVERRIDES.with(|v| {
+            let mut map = v.borrow_mut();
+            map.entry(self.id).or_default().insert(key.as_ref().into(), value.as_ref().into());
+        });
+    }
+
+    /// Removes all the thread-local overrides of the current thread.
+    ///
+    /// See [clear_thread_overrides](crate::clear_thread_overrides) for details.
+    pub fn clear_thread_overrides(&self) {
+        THREAD_OVERRIDES.with(|v| v.borrow_mut().remove(&self.id));
+    }
+
+    /// Returns the thread-local override of a variable for the current thread.
+    fn thread_override(&self, name: &OsStr) -> Option<OsString> {
+        THREAD_OVERRIDES.with(|v| v.borrow().get(&self.id)?.get(KeyRef::new(name)).cloned())
+    }
+
+    /// Overrides the value of a variable.
+    ///
+    /// An empty value is treated as a defined but empty variable. The override is stored in the
+    /// cache and is therefore wiped whenever the cache is invalidated.
+    pub fn set_override<K: AsRef<OsStr>, V: AsRef<OsStr>>(&self, key: K, value: V) {
+        self.check_frozen();
+        let mut lock = self.cache.write().unwrap();
+        lock.insert(key.as_ref().into(), Entry::new(Some(value.as_ref().into())));
+    }
+
+    /// Overrides the values of several variables at once.
+    ///
+    /// See [set_many](crate::set_many) for details.
+    pub fn set_many<I, K, V>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>
+    {
+        self.check_frozen();
+        // Collect the entries first to avoid holding the lock while running the iterator.
+        let entries: Vec<(Key, Entry)> = entries
+            .into_iter()
+            .map(|(k, v)| (k.as_ref().into(), Entry::new(Some(v.as_ref().into()))))
+            .collect();
+        let mut lock = self.cache.write().unwrap();
+        lock.extend(entries);
+    }
+
+    /// Overrides a variable to be unset.
+    ///
+    /// The override is stored in the cache and is therefore wiped whenever the cache is
+    /// invalidated.
+    pub fn unset_override<K: AsRef<OsStr>>(&self, key: K) {
+        self.check_frozen();
+        let mut lock = self.cache.write().unwrap();
+        lock.insert(key.as_ref().into(), Entry::new(None));
+    }
+
+    /// Overrides the value of a variable until the returned guard is dropped.
+    ///
+    /// When the guard is dropped, including during a panic, the previous programmatic override of
+    /// the variable is restored. If the variable was not overridden, its cache entry is removed so
+    /// that the next lookup resolves it again from the current sources.
+    pub fn scoped_override<K: AsRef<OsStr>, V: AsRef<OsStr>>(
+        &self,
+        key: K,
+        value: V
+    ) -> OverrideGuard<'_> {
+        self.check_frozen();
+        let key = Key::from(key.as_ref());
+        let mut lock = self.cache.write().unwrap();
+        // Resolved entries are not restored as the sources may change before the guard is dropped.
+        let previous = lock.get(&key).filter(|v| matches!(v.origin, Origin::Override)).cloned();
+        lock.insert(key.clone(), Entry::new(Some(value.as_ref().into())));
+        OverrideGuard {
+            env: self,
+            key,
+            previous,
+        }
+    }
+
+    /// Enables or disables `${NAME}` variable interpolation in override files.
+    ///
+    /// Interpolation is disabled by default. Changing this setting invalidates the cache.
+    pub fn set_interpolation(&self, enabled: bool) {
+        self.configure(|config| config.interpolation = enabled);
+    }
+
+    /// Enables or disables `%NAME%` variable interpolation in override files.
+    ///
+    /// See [set_percent_interpolation](crate::set_percent_interpolation) for details.
+    pub fn set_percent_interpolation(&self, enabled: bool) {
+        self.configure(|config| config.percent_interpolation = enabled);
+    }
+
+    /// Sets whether override files take precedence over the process environment.
+    ///
+    /// By default, the process environment is consulted before override files. Changing this
+    /// setting invalidates the cache.
+    pub fn set_file_precedence(&self, enabled: bool) {
+        self.configure(|config| config.file_precedence = enabled);
+    }
+
+    /// Sets the byte separating keys from values in override files.
+    ///
+    /// The default delimiter is `=`. Changing this setting invalidates the cache.
+    pub fn set_delimiter(&self, delimiter: u8) {
+        self.configure(|config| config.delimiter = delimiter);
+    }
+
+    /// Sets which definition of a key is used when it's defined several times in the same override
+    /// file.
+    ///
+    /// The default is [DuplicatePolicy::FirstWins]. Changing this setting invalidates the cache.
+    pub fn set_duplicate_policy(&self, policy: DuplicatePolicy) {
+        self.configure(|config| config.duplicates = policy);
+    }
+
+    /// Sets the maximum size in bytes of the override files which are read.
+    ///
+    /// See [set_max_file_size](crate::set_max_file_size) for details.
+    pub fn set_max_file_size(&self, max_size: Option<u64>) {
+        self.configure(|config| config.max_file_size = max_size);
+    }
+
+    /// Sets the maximum number of entries in the cache.
+    ///
+    /// See [set_max_cache_entries](crate::set_max_cache_entries) for details.
+    pub fn set_max_cache_entries(&self, max: Option<usize>) {
+        self.configure(|config| config.max_cache_entries = max);
+    }
+
+    /// Sets whether variables with an empty value are returned as present-empty by the getters.
+    ///
+    /// By default, empty values are treated as unset. Changing this setting invalidates the
+    /// cache.
+    pub fn set_keep_empty(&self, enabled: bool) {
+        self.configure(|config| config.keep_empty = enabled);
+    }
+
+    /// Sets whether the getters check the modification time of override files.
+    ///
+    /// See [set_mtime_revalidation](crate::set_mtime_revalidation) for details.
+    pub fn set_mtime_revalidation(&self, enabled: bool) {
+        self.configure(|config| config.mtime_revalidation = enabled);
+    }
+
+    /// Sets for how long a variable which was not found in any source is remembered as missing.
+    ///
+    /// When a missing variable is looked up again after the TTL has elapsed, every source is
+    /// consulted again, which allows picking up variables defined later without calling
+    /// [clear_cache](Self::clear_cache). The default is None: missing variables are remembered
+    /// until the cache is invalidated. Programmatic overrides never expire. Changing this setting
+    /// invalidates the cache.
+    pub fn set_negative_ttl(&self, ttl: Option<Duration>) {
+        self.configure(|config| config.negative_ttl = ttl);
+    }
+
+    /// Sets a prefix prepended to every variable name passed to the getters.
+    ///
+    /// See [set_prefix](crate::set_prefix) for details.
+    pub fn set_prefix<T: AsRef<OsStr>>(&self, prefix: T) {
+        *self.prefix.write().unwrap() = prefix.as_ref().into();
+    }
+
+    /// Prepends the configured prefix to a variable name.
+    fn prefixed<'a>(&self, name: &'a OsStr) -> Cow<'a, OsStr> {
+        let prefix = self.prefix.read().unwrap();
+        if prefix.is_empty() {
+            return Cow::Borrowed(name);
+        }
+        let mut res = prefix.clone();
+        res.push(name);
+        Cow::Owned(res)
+    }
+
+    /// Looks up a variable, returning empty values as is.
+    fn lookup(&self, name: &OsStr) -> Option<OsString> {
+        self.lookup_with(name, |v| v.map(OsStr::to_os_string))
+    }
+
+    /// Resolves a variable and passes its value to f, without cloning it when it is cached.
+    fn lookup_with<R>(&self, name: &OsStr, f: impl FnOnce(Option<&OsStr>) -> R) -> R {
+        let name = &*self.prefixed(name);
+        if let Some(value) = self.thread_override(name) {
+            debug!(?name, "resolved variable from thread-local override");
+            return f(Some(&value));
+        }
+        self.revalidate();
+        {
+            // Attempt to pull from the cache, this only requires a read lock.
+            let cache = self.cache.read().unwrap();
+            if let Some(entry) = cache.get(KeyRef::new(name)) {
+                if !entry.is_expired(self.config().negative_ttl) {
+                    debug!(?name, "resolved variable from cache");
+                    self.stats.hits.fetch_add(1, Ordering::Relaxed);
+                    return f(entry.value.as_deref());
+                }
+            }
+        }
+        // Value is not in cache, take the write lock; resolve checks the cache again as another
+        // thread may have resolved the same variable in the mean time. The source list is always
+        // locked before the cache to avoid dead-locks.
+        let sources = self.sources.lock().unwrap();
+        let mut cache = self.cache.write().unwrap();
+        f(Resolver::new(self, &mut cache, &sources).resolve(name).as_deref())
+    }
+
+    /// Gets the content of an environment variable.
+    ///
+    /// Returns None if the variable does not exist or is empty.
+    ///
+    /// The cost of this function is amortized O(1) (thanks to the cache). When a value is not
+    /// loaded the cost of this function is O(nm) with n the number of items in the override path
+    /// list and m the number of lines in each override file.
+    pub fn get_os<T: AsRef<OsStr>>(&self, name: T) -> Option<OsString> {
+        let config = self.config();
+        self.lookup(name.as_ref()).filter(|v| config.accepts(v))
+    }
+
+    /// Passes the content of an environment variable to f without cloning it.
+    ///
+    /// See [with_value](crate::with_value) for details.
+    pub fn with_value<T: AsRef<OsStr>, R>(
+        &self,
+        name: T,
+        f: impl FnOnce(Option<&OsStr>) -> R
+    ) -> R {
+        let config = self.config();
+        self.lookup_with(name.as_ref(), |v| f(v.filter(|v| config.accepts(v))))
+    }
+
+    /// Gets the content of an environment variable together with its source.
+    ///
+    /// Returns None if the variable does not exist, is empty or is not valid UTF-8.
+    pub fn get_with_source<T: AsRef<OsStr>>(&self, name: T) -> Option<(String, ValueSource)> {
+        let name = self.prefixed(name.as_ref());
+        let config = self.config();
+        if let Some(value) = self.thread_override(&name) {
+            let value = Some(value).filter(|v| config.accepts(v))?.into_string().ok()?;
+            return Some((value, ValueSource::Programmatic));
+        }
+        self.revalidate();
+        let sources = self.sources.lock().unwrap();
+        let mut cache = self.cache.write().unwrap();
+        let entry = Resolver::new(self, &mut cache, &sources).resolve_entry(&name);
+        let source = match entry.origin {
+            Origin::Override => ValueSource::Programmatic,
+            Origin::Process => ValueSource::ProcessEnv,
+            Origin::Source(i) => match sources.get(i).and_then(Source::path) {
+                Some(path) => ValueSource::File(path.into()),
+                None => ValueSource::Memory,
+            },
+            Origin::Missing(_) => return None,
+        };
+        let value = entry.value.filter(|v| config.accepts(v))?.into_string().ok()?;
+        Some((value, source))
+    }
+
+    /// Returns true if the variable is defined in any source, even if its value is empty.
+    ///
+    /// This has the same cost as [get_os](Self::get_os).
+    pub fn is_set<T: AsRef<OsStr>>(&self, name: T) -> bool {
+        self.lookup_with(name.as_ref(), |v| v.is_some())
+    }
+
+    /// Returns true if [get_os](Self::get_os) would return a value for this variable.
+    ///
+    /// Unlike [get_os](Self::get_os), the value is never cloned out of the cache. Present but
+    /// empty variables do not exist unless empty values are kept (see
+    /// [set_keep_empty](Self::set_keep_empty)); use [is_set](Self::is_set) to also detect them.
+    pub fn exists<T: AsRef<OsStr>>(&self, name: T) -> bool {
+        let config = self.config();
+        self.lookup_with(name.as_ref(), |v| v.map(|v| config.accepts(v)).unwrap_or(false))
+    }
+
+    /// Gets the content of an environment variable.
+    ///
+    /// Returns None if the variable does not exist or is not valid UTF-8.
+    pub fn get<T: AsRef<OsStr>>(&self, name: T) -> Option<String> {
+        self.get_os(name).and_then(|v| v.into_string().ok())
+    }
+
+    /// Gets the content of an environment variable without consulting or filling the cache.
+    ///
+    /// See [get_uncached](crate::get_uncached) for details.
+    pub fn get_uncached<T: AsRef<OsStr>>(&self, name: T) -> Option<String> {
+        let name = self.prefixed(name.as_ref());
+        let config = self.config();
+        if let Some(value) = self.thread_override(&name) {
+            return Some(value).filter(|v| config.accepts(v))?.into_string().ok();
+        }
+        let sources = self.sources.lock().unwrap();
+        // Programmatic overrides only live in the cache, they are the only entries kept.
+        let mut cache: Cache = self
+            .cache
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, v)| matches!(v.origin, Origin::Override))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Resolver::new(self, &mut cache, &sources)
+            .resolve(&name)
+            .filter(|v| config.accepts(v))
+            .and_then(|v| v.into_string().ok())
+    }
+
+    /// Gets a boolean environment variable.
+    ///
+    /// Returns None if the variable does not exist or the format is unrecognized.
+    ///
+    /// Recognized values are `on`, `true`, `yes`, `y` and `1` for true and `off`, `false`, `no`,
+    /// `n` and `0` for false. Values are matched case-insensitively.
+    pub fn get_bool<T: AsRef<OsStr>>(&self, name: T) -> Option<bool> {
+        parser::parse_bool(&self.get(name)?)
+    }
+
+    /// Gets a boolean environment variable, or default if it's unset, empty or unrecognized.
+    ///
+    /// See [get_bool_with_default](crate::get_bool_with_default) for details.
+    pub fn get_bool_with_default<T: AsRef<OsStr>>(&self, name: T, default: bool) -> bool {
+        self.get_bool(name).unwrap_or(default)
+    }
+
+    /// Loads every variable defined in the override sources into the cache.
+    ///
+    /// See [preload](crate::preload) for details.
+    pub fn preload(&self) {
+        let sources = self.sources.lock().unwrap();
+        let keys = source_keys(&sources, &self.config());
+        let mut cache = self.cache.write().unwrap();
+        let mut resolver = Resolver::new(self, &mut cache, &sources);
+        for key in keys {
+            resolver.resolve(&key);
+        }
+    }
+
+    /// Returns all variables currently known by this environment.
+    ///
+    /// Variables with an empty value are omitted. The values resolved by this function are not
+    /// inserted in the cache.
+    ///
+    /// **Note: this is a heavy function which eagerly reads every override file and resolves
+    /// every variable found.**
+    pub fn snapshot(&self) -> HashMap<OsString, OsString> {
+        let sources = self.sources.lock().unwrap();
+        let mut cache = self.cache.read().unwrap().clone();
+        let mut keys = process_keys();
+        keys.extend(source_keys(&sources, &self.config()));
+        keys.extend(cache.keys().map(|k| k.as_os_str().to_os_string()));
+        let config = self.config();
+        let mut resolver = Resolver::new(self, &mut cache, &sources);
+        // Deduplicate keys which only differ by case on platforms with case-insensitive names.
+        let map: HashMap<Key, OsString> = keys
+            .into_iter()
+            .filter_map(|k| {
+                let value = resolver.resolve(&k).filter(|v| config.accepts(v))?;
+                Some((k.into(), value))
+            })
+            .collect();
+        map.into_iter().map(|(k, v)| (k.into_os_string(), v)).collect()
+    }
+
+    /// Returns all variables whose name starts with prefix, keyed by the rest of their name.
+    ///
+    /// See [get_prefixed](crate::get_prefixed) for details.
+    pub fn get_prefixed<T: AsRef<OsStr>>(&self, prefix: T) -> HashMap<String, String> {
+        let prefix = match prefix.as_ref().to_str() {
+            Some(v) => v,
+            None => return HashMap::new(),
+        };
+        self.snapshot()
+            .into_iter()
+            .filter_map(|(k, v)| {
+                let key = k.into_string().ok()?;
+                let suffix = key.strip_prefix(prefix)?.into();
+                Some((suffix, v.into_string().ok()?))
+            })
+            .collect()
+    }
+
+    /// Returns statistics about the cache of this environment.
+    ///
+    /// See [cache_stats](crate::cache_stats) for details.
+    pub fn cache_stats(&self) -> CacheStats {
+        let cache = self.cache.read().unwrap();
+        CacheStats {
+            hits: self.stats.hits.load(Ordering::Relaxed),
+            misses: self.stats.misses.load(Ordering::Relaxed),
+            source_reads: self.stats.source_reads.load(Ordering::Relaxed),
+            entries: cache.len(),
+            negative_entries: cache
+                .values()
+                .filter(|v| matches!(v.origin, Origin::Missing(_)))
+                .count(),
+        }
+    }
+
+    /// Returns the names of all variables currently known by this environment, sorted.
+    ///
+    /// Variables with an empty value are omitted.
+    ///
+    /// **Note: this is a heavy function with the same cost as [snapshot](Self::snapshot).**
+    pub fn keys(&self) -> Vec<OsString> {
+        let mut keys: Vec<OsString> = self.snapshot().into_keys().collect();
+        keys.sort();
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a file in the temporary directory, unique to this process.
+    fn write_file(name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("bp3d_env_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn scoped_override_does_not_restore_stale_entries() {
+        let env = Environment::new();
+        let a = write_file("scoped_a.env", "");
+        let b = write_file("scoped_b.env", "SCOPED_GK=fromb\n");
+        env.add_override_path(&a);
+        env.add_override_path(&b);
+        assert_eq!(env.get("SCOPED_GK").as_deref(), Some("fromb"));
+        let guard = env.scoped_override("SCOPED_GK", "tmp");
+        assert_eq!(env.get("SCOPED_GK").as_deref(), Some("tmp"));
+        assert!(env.remove_override_path(&b));
+        drop(guard);
+        assert_eq!(env.get("SCOPED_GK"), None);
+    }
+
+    #[test]
+    fn get_with_source_ignores_entries_of_removed_sources() {
+        let env = Environment::new();
+        let a = write_file("source_a.env", "SOURCE_GK=froma\n");
+        env.add_override_path(&a);
+        let stale = Entry {
+            value: Some("stale".into()),
+            origin: Origin::Source(1),
+        };
+        env.cache.write().unwrap().insert(OsStr::new("SOURCE_GK").into(), stale);
+        let (value, source) = env.get_with_source("SOURCE_GK").unwrap();
+        assert_eq!(value, "froma");
+        assert_eq!(source, ValueSource::File(a));
+    }
+
+    #[test]
+    fn get_with_source_after_scoped_override_and_removal() {
+        let env = Environment::new();
+        let a = write_file("removal_a.env", "");
+        let b = write_file("removal_b.env", "REMOVAL_GK=fromb\n");
+        env.add_override_path(&a);
+        env.add_override_path(&b);
+        assert_eq!(
+            env.get_with_source("REMOVAL_GK"),
+            Some(("fromb".into(), ValueSource::File(b.clone())))
+        );
+        let guard = env.scoped_override("REMOVAL_GK", "tmp");
+        env.remove_override_path(&b);
+        drop(guard);
+        assert_eq!(env.get_with_source("REMOVAL_GK"), None);
+    }
+
+    #[test]
+    fn preload_caches_variables_after_a_cycle() {
+        let env = Environment::new();
+        env.set_interpolation(true);
+        env.add_override_str("CYCLE_X=${CYCLE_Y}\nCYCLE_Y=${CYCLE_X}\nCYCLE_P=1\nCYCLE_Q=2\n");
+        env.preload();
+        let cache = env.cache.read().unwrap();
+        assert!(cache.contains_key(KeyRef::new(OsStr::new("CYCLE_P"))));
+        assert!(cache.contains_key(KeyRef::new(OsStr::new("CYCLE_Q"))));
+        assert!(!cache.contains_key(KeyRef::new(OsStr::new("CYCLE_X"))));
+        assert!(!cache.contains_key(KeyRef::new(OsStr::new("CYCLE_Y"))));
+    }
+
+    #[test]
+    fn cycle_does_not_prevent_caching_unrelated_references() {
+        let env = Environment::new();
+        env.set_interpolation(true);
+        env.add_override_str("REF_A=${REF_B}${REF_C}\nREF_B=${REF_B}\nREF_C=c\n");
+        assert_eq!(env.get("REF_A").as_deref(), Some("${REF_B}c"));
+        let cache = env.cache.read().unwrap();
+        assert!(cache.contains_key(KeyRef::new(OsStr::new("REF_A"))));
+        assert!(cache.contains_key(KeyRef::new(OsStr::new("REF_C"))));
+        assert!(!cache.contains_key(KeyRef::new(OsStr::new("REF_B"))));
+    }
+
+    #[test]
+    fn continuation_after_escaped_backslash() {
+        let env = Environment::new();
+        env.add_override_str("CONT_A=x\\\\\\\nCONT_B=y\nCONT_C=z\\\\\nCONT_D=w\n");
+        assert_eq!(env.get("CONT_A").as_deref(), Some("x\\\\CONT_B=y"));
+        assert_eq!(env.get("CONT_B"), None);
+        assert_eq!(env.get("CONT_C").as_deref(), Some("z\\\\"));
+        assert_eq!(env.get("CONT_D").as_deref(), Some("w"));
+    }
+
+    #[test]
+    fn get_bool_with_default_unset() {
+        let env = Environment::new();
+        assert!(env.get_bool_with_default("BOOL_DEFAULT_UNSET", true));
+        assert!(!env.get_bool_with_default("BOOL_DEFAULT_UNSET", false));
+    }
+
+    #[test]
+    fn get_bool_with_default_empty() {
+        let env = Environment::new();
+        env.add_override_str("BOOL_DEFAULT_EMPTY=\n");
+        assert!(env.get_bool_with_default("BOOL_DEFAULT_EMPTY", true));
+        assert!(!env.get_bool_with_default("BOOL_DEFAULT_EMPTY", false));
+        env.set_keep_empty(true);
+        assert!(env.get_bool_with_default("BOOL_DEFAULT_EMPTY", true));
+    }
+
+    #[test]
+    fn get_bool_with_default_recognized() {
+        let env = Environment::new();
+        env.add_override_str("BOOL_DEFAULT_YES=Yes\nBOOL_DEFAULT_OFF=off\n");
+        assert!(env.get_bool_with_default("BOOL_DEFAULT_YES", false));
+        assert!(!env.get_bool_with_default("BOOL_DEFAULT_OFF", true));
+    }
+
+    #[test]
+    fn get_bool_with_default_unrecognized() {
+        let env = Environment::new();
+        env.add_override_str("BOOL_DEFAULT_MAYBE=maybe\n");
+        assert!(env.get_bool_with_default("BOOL_DEFAULT_MAYBE", true));
+        assert!(!env.get_bool_with_default("BOOL_DEFAULT_MAYBE", false));
+    }
+
+    #[test]
+    fn concurrent_readers_do_not_block_each_other() {
+        use std::sync::{mpsc, Arc};
+        let env = Arc::new(Environment::new());
+        env.set_override("CONCURRENT_WARM", "value");
+        // Hold a read lock on the cache while another thread reads the same warm key: with an
+        // exclusive lock the other thread would block until the closure returns.
+        env.with_value("CONCURRENT_WARM", |value| {
+            assert_eq!(value, Some(OsStr::new("value")));
+            let (sender, receiver) = mpsc::channel();
+            let other = env.clone();
+            std::thread::spawn(move || {
+                sender.send(other.get("CONCURRENT_WARM")).unwrap();
+            });
+            let value = receiver.recv_timeout(Duration::from_secs(10));
+            assert_eq!(value.unwrap().as_deref(), Some("value"));
+        });
+    }
+
+    #[test]
+    fn concurrent_misses_resolve_once() {
+        use std::sync::{Arc, Barrier};
+        const THREADS: usize = 8;
+        let env = Arc::new(Environment::new());
+        let path = write_file("concurrent_miss.env", "CONCURRENT_MISS=value\n");
+        env.add_override_path(&path);
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let env = env.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    env.get("CONCURRENT_MISS")
+                })
+            })
+            .collect();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap().as_deref(), Some("value"));
+        }
+        // The double-checked insert resolves the variable only once: every other thread either
+        // hits the cache directly or finds the entry after taking the write lock.
+        let stats = env.cache_stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.source_reads, 1);
+        assert_eq!(stats.hits, THREADS as u64 - 1);
+        assert_eq!(stats.entries, 1);
+    }
+
+    #[test]
+    fn with_value_matches_get_os() {
+        let env = Environment::new();
+        env.add_override_str("WITH_VALUE_FILE=file\nWITH_VALUE_EMPTY=\n");
+        env.set_override("WITH_VALUE_OVERRIDE", "override");
+        for name in [
+            "WITH_VALUE_FILE",
+            "WITH_VALUE_EMPTY",
+            "WITH_VALUE_OVERRIDE",
+            "WITH_VALUE_NONE"
+        ] {
+            // The first call resolves the variable and the second one reads it from the cache.
+            for _ in 0..2 {
+                let value = env.with_value(name, |v| v.map(OsStr::to_os_string));
+                assert_eq!(value, env.get_os(name), "{name}");
+            }
+        }
+        assert_eq!(env.with_value("WITH_VALUE_FILE", |v| v.map(OsStr::len)), Some(4));
+    }
+
+    #[test]
+    fn scoped_override_restores_previous_override() {
+        let env = Environment::new();
+        env.set_override("SCOPED_PREVIOUS", "first");
+        let guard = env.scoped_override("SCOPED_PREVIOUS", "second");
+        assert_eq!(env.get("SCOPED_PREVIOUS").as_deref(), Some("second"));
+        drop(guard);
+        assert_eq!(env.get("SCOPED_PREVIOUS").as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn comments_in_override_files() {
+        let env = Environment::new();
+        env.add_override_str("# COMMENT_A=1\nCOMMENT_B=value # note\nCOMMENT_C=\"a # b\"\n");
+        assert_eq!(env.get("# COMMENT_A"), None);
+        assert_eq!(env.get("COMMENT_A"), None);
+        assert_eq!(env.get("COMMENT_B").as_deref(), Some("value"));
+        assert_eq!(env.get("COMMENT_C").as_deref(), Some("a # b"));
+    }
+
+    #[test]
+    fn unset_in_front_file_hides_back_file() {
+        let env = Environment::new();
+        let front = write_file("unset_front.env", "@unset UNSET_FRONT\n");
+        let back = write_file("unset_back.env", "UNSET_FRONT=back\nUNSET_OTHER=other\n");
+        env.add_override_path(&front);
+        env.add_override_path(&back);
+        assert_eq!(env.get("UNSET_FRONT"), None);
+        assert!(!env.is_set("UNSET_FRONT"));
+        assert_eq!(env.get("UNSET_OTHER").as_deref(), Some("other"));
+        assert!(!env.keys().contains(&OsString::from("UNSET_FRONT")));
+    }
+
+    #[test]
+    fn unset_in_back_file_does_not_hide_front_file() {
+        let env = Environment::new();
+        let front = write_file("unset_back_front.env", "UNSET_BACK=front\n");
+        let back = write_file("unset_back_back.env", "@unset UNSET_BACK\n");
+        env.add_override_path(&front);
+        env.add_override_path(&back);
+        assert_eq!(env.get("UNSET_BACK").as_deref(), Some("front"));
+    }
+
+    #[test]
+    fn unset_hides_included_files() {
+        let env = Environment::new();
+        let included = write_file("unset_included.env", "UNSET_INCLUDED=included\n");
+        let contents = format!("@unset UNSET_INCLUDED\n@include {}\n", included.display());
+        env.add_override_str(&contents);
+        assert_eq!(env.get("UNSET_INCLUDED"), None);
+    }
+
+    #[test]
+    fn unset_and_duplicate_policy() {
+        let env = Environment::new();
+        env.add_override_str("UNSET_DUP=1\n@unset UNSET_DUP\n");
+        assert_eq!(env.get("UNSET_DUP").as_deref(), Some("1"));
+        env.set_duplicate_policy(DuplicatePolicy::LastWins);
+        assert_eq!(env.get("UNSET_DUP"), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-process-env"))]
+    fn unset_falls_through_to_process_environment() {
+        let env = Environment::new();
+        std::env::set_var("UNSET_PROCESS", "process");
+        env.add_override_str("@unset UNSET_PROCESS\n");
+        env.add_override_str("UNSET_PROCESS=file\n");
+        env.set_file_precedence(true);
+        assert_eq!(env.get("UNSET_PROCESS").as_deref(), Some("process"));
+        env.set_file_precedence(false);
+        assert_eq!(env.get("UNSET_PROCESS").as_deref(), Some("process"));
+    }
+
+    #[test]
+    fn duplicate_keys_first_wins_by_default() {
+        let env = Environment::new();
+        let path = write_file("duplicates.env", "DUP_KEY=a\nDUP_OTHER=x\nDUP_KEY=b\n");
+        env.add_override_path(&path);
+        assert_eq!(env.get("DUP_KEY").as_deref(), Some("a"));
+        let entries: Vec<_> = crate::parse_file(&path).unwrap();
+        assert_eq!(entries.iter().filter(|(k, _)| k == "DUP_KEY").count(), 2);
+    }
+
+    #[test]
+    fn duplicate_keys_last_wins() {
+        let env = Environment::new();
+        let path = write_file("duplicates_last.env", "DUP_LAST=a\nDUP_LAST=b\nDUP_LAST=c\n");
+        env.add_override_path(&path);
+        env.set_duplicate_policy(DuplicatePolicy::LastWins);
+        assert_eq!(env.get("DUP_LAST").as_deref(), Some("c"));
+        // Changing the policy invalidates the cache.
+        env.set_duplicate_policy(DuplicatePolicy::FirstWins);
+        assert_eq!(env.get("DUP_LAST").as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn duplicate_policy_only_applies_within_a_file() {
+        let env = Environment::new();
+        env.add_override_str("DUP_FILES=first\n");
+        env.add_override_str("DUP_FILES=second\nDUP_FILES=third\n");
+        env.set_duplicate_policy(DuplicatePolicy::LastWins);
+        assert_eq!(env.get("DUP_FILES").as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn negative_entries_expire_after_ttl() {
+        let env = Environment::new();
+        let path = write_file("negative_ttl.env", "");
+        env.add_override_path(&path);
+        env.set_negative_ttl(Some(Duration::from_millis(50)));
+        assert_eq!(env.get("NEGATIVE_TTL_LATE"), None);
+        std::fs::write(&path, "NEGATIVE_TTL_LATE=late\n").unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(env.get("NEGATIVE_TTL_LATE").as_deref(), Some("late"));
+    }
+
+    #[test]
+    fn negative_entries_are_permanent_by_default() {
+        let env = Environment::new();
+        let path = write_file("negative_permanent.env", "");
+        env.add_override_path(&path);
+        assert_eq!(env.get("NEGATIVE_PERMANENT"), None);
+        std::fs::write(&path, "NEGATIVE_PERMANENT=late\n").unwrap();
+        assert_eq!(env.get("NEGATIVE_PERMANENT"), None);
+        env.clear_cache();
+        assert_eq!(env.get("NEGATIVE_PERMANENT").as_deref(), Some("late"));
+    }
+
+    #[test]
+    fn negative_ttl_does_not_expire_unset_overrides() {
+        let env = Environment::new();
+        env.add_override_str("NEGATIVE_UNSET=file\n");
+        env.set_negative_ttl(Some(Duration::ZERO));
+        env.unset_override("NEGATIVE_UNSET");
+        assert_eq!(env.get("NEGATIVE_UNSET"), None);
+    }
+
+    #[test]
+    fn reload_path_keeps_unrelated_keys() {
+        let env = Environment::new();
+        let a = write_file("reload_a.env", "RELOAD_A=a1\n");
+        let b = write_file("reload_b.env", "RELOAD_B=b1\n");
+        env.add_override_path(&a);
+        env.add_override_path(&b);
+        env.set_override("RELOAD_OVERRIDE", "override");
+        assert_eq!(env.get("RELOAD_A").as_deref(), Some("a1"));
+        assert_eq!(env.get("RELOAD_B").as_deref(), Some("b1"));
+        std::fs::write(&a, "RELOAD_A=a2\n").unwrap();
+        std::fs::write(&b, "RELOAD_B=b2\n").unwrap();
+        env.reload_path(&b);
+        // RELOAD_A comes from a file before the reloaded one and stays cached.
+        assert_eq!(env.get("RELOAD_A").as_deref(), Some("a1"));
+        assert_eq!(env.get("RELOAD_B").as_deref(), Some("b2"));
+        assert_eq!(env.get("RELOAD_OVERRIDE").as_deref(), Some("override"));
+    }
+
+    #[test]
+    fn reload_path_evicts_missing_entries() {
+        let env = Environment::new();
+        let a = write_file("reload_missing.env", "");
+        env.add_override_path(&a);
+        assert_eq!(env.get("RELOAD_MISSING"), None);
+        std::fs::write(&a, "RELOAD_MISSING=now\n").unwrap();
+        env.reload_path(&a);
+        assert_eq!(env.get("RELOAD_MISSING").as_deref(), Some("now"));
+    }
+
+    #[test]
+    fn reload_unknown_path_does_nothing() {
+        let env = Environment::new();
+        env.add_override_str("RELOAD_UNKNOWN=1\n");
+        assert_eq!(env.get("RELOAD_UNKNOWN").as_deref(), Some("1"));
+        env.reload_path("does_not_exist.env");
+        assert_eq!(env.cache_stats().entries, 1);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn utf16_le_override_file() {
+        let env = Environment::new();
+        let mut data = vec![0xFF, 0xFE];
+        for v in "UTF16_KEY=välue\r\nUTF16_OTHER=1\r\n".encode_utf16() {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        let path = write_file("utf16.env", data);
+        env.add_override_path(&path);
+        assert_eq!(env.get("UTF16_KEY").as_deref(), Some("välue"));
+        assert_eq!(env.get("UTF16_OTHER").as_deref(), Some("1"));
+        assert!(env.validate_path(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn keys_are_case_insensitive_on_windows() {
+        let env = Environment::new();
+        env.add_override_str("case_fold_path=from file\n");
+        assert_eq!(env.get("CASE_FOLD_PATH").as_deref(), Some("from file"));
+        assert_eq!(env.get("Case_Fold_Path").as_deref(), Some("from file"));
+        assert_eq!(env.get("case_fold_path").as_deref(), Some("from file"));
+        env.set_override("CASE_FOLD_PATH", "override");
+        assert_eq!(env.get("case_fold_path").as_deref(), Some("override"));
+        assert_eq!(env.cache_stats().entries, 1);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn keys_are_case_sensitive_on_unix() {
+        let env = Environment::new();
+        env.add_override_str("case_fold_path=from file\n");
+        assert_eq!(env.get("CASE_FOLD_PATH"), None);
+        assert_eq!(env.get("case_fold_path").as_deref(), Some("from file"));
+    }
+
+    #[test]
+    #[cfg(feature = "no-process-env")]
+    fn process_environment_is_never_read() {
+        let env = Environment::new();
+        std::env::set_var("NO_PROCESS_ENV_ONLY", "process");
+        std::env::set_var("NO_PROCESS_ENV_BOTH", "process");
+        env.add_override_str("NO_PROCESS_ENV_BOTH=file\n");
+        assert_eq!(env.get("NO_PROCESS_ENV_ONLY"), None);
+        assert_eq!(env.get("NO_PROCESS_ENV_BOTH").as_deref(), Some("file"));
+        assert!(!env.keys().contains(&OsString::from("NO_PROCESS_ENV_ONLY")));
+        env.set_override("NO_PROCESS_ENV_ONLY", "override");
+        assert_eq!(env.get("NO_PROCESS_ENV_ONLY").as_deref(), Some("override"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-process-env"))]
+    fn process_environment_is_read() {
+        let env = Environment::new();
+        std::env::set_var("PROCESS_ENV_ONLY", "process");
+        std::env::set_var("PROCESS_ENV_BOTH", "process");
+        env.add_override_str("PROCESS_ENV_BOTH=file\n");
+        assert_eq!(env.get("PROCESS_ENV_ONLY").as_deref(), Some("process"));
+        assert_eq!(env.get("PROCESS_ENV_BOTH").as_deref(), Some("process"));
+        env.set_file_precedence(true);
+        assert_eq!(env.get("PROCESS_ENV_BOTH").as_deref(), Some("file"));
+    }
+
+    #[test]
+    fn add_override_path_accepts_missing_files() {
+        let env = Environment::new();
+        let name = format!("bp3d_env_{}_missing.env", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        assert!(matches!(env.try_add_override_path(&path), Err(AddPathError::Io(_))));
+        env.add_override_path(&path);
+        assert_eq!(env.override_paths(), vec![path.clone()]);
+        assert_eq!(env.get("MISSING_GK"), None);
+        std::fs::write(&path, "MISSING_GK=late\n").unwrap();
+        env.clear_cache();
+        assert_eq!(env.get("MISSING_GK").as_deref(), Some("late"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot add environment override path")]
+    fn add_override_path_rejects_directories() {
+        let env = Environment::new();
+        env.add_override_path(std::env::temp_dir());
+    }
+
+    #[test]
+    fn crlf_line_endings_are_stripped() {
+        let env = Environment::new();
+        let path = write_file("crlf.env", "CRLF_A=first\r\nCRLF_B=second\r\n");
+        env.add_override_path(&path);
+        assert_eq!(env.get("CRLF_A").as_deref(), Some("first"));
+        assert_eq!(env.get("CRLF_B").as_deref(), Some("second"));
+    }
+
+    #[test]
+    fn utf8_bom_is_stripped_from_the_first_line() {
+        let env = Environment::new();
+        let path = write_file("bom.env", b"\xEF\xBB\xBFBOM_KEY=value\nBOM_OTHER=other\n");
+        env.add_override_path(&path);
+        assert_eq!(env.get("BOM_KEY").as_deref(), Some("value"));
+        assert_eq!(env.get("BOM_OTHER").as_deref(), Some("other"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-process-env"))]
+    fn file_precedence_orderings() {
+        let env = Environment::new();
+        std::env::set_var("PRECEDENCE_BOTH", "process");
+        let path = write_file("precedence.env", "PRECEDENCE_BOTH=file\n");
+        env.add_override_path(&path);
+        assert_eq!(env.get("PRECEDENCE_BOTH").as_deref(), Some("process"));
+        env.set_file_precedence(true);
+        assert_eq!(env.get("PRECEDENCE_BOTH").as_deref(), Some("file"));
+        env.set_file_precedence(false);
+        assert_eq!(env.get("PRECEDENCE_BOTH").as_deref(), Some("process"));
+    }
+
+    #[test]
+    fn custom_delimiter() {
+        let env = Environment::new();
+        let colon = write_file("colon.env", "COLON_KEY: value\nCOLON_URL: http://host:80\n");
+        let equal = write_file("equal.env", "EQUAL_KEY=value\n");
+        env.add_override_path(&equal);
+        assert_eq!(env.get("EQUAL_KEY").as_deref(), Some("value"));
+        env.set_delimiter(b':');
+        env.add_override_path(&colon);
+        assert_eq!(env.get("COLON_KEY").as_deref(), Some("value"));
+        assert_eq!(env.get("COLON_URL").as_deref(), Some("http://host:80"));
+    }
+
+    #[test]
+    fn preload_survives_file_deletion() {
+        let env = Environment::new();
+        let path = write_file("preload.env", "PRELOAD_A=a\nPRELOAD_B=${PRELOAD_A}b\n");
+        env.add_override_path(&path);
+        env.set_interpolation(true);
+        env.preload();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(env.get("PRELOAD_A").as_deref(), Some("a"));
+        assert_eq!(env.get("PRELOAD_B").as_deref(), Some("ab"));
+        assert_eq!(env.get("PRELOAD_MISSING"), None);
+    }
+
+    #[test]
+    fn get_with_source_variants() {
+        let env = Environment::new();
+        let path = write_file("variants.env", "VARIANT_FILE=file\n");
+        env.add_override_path(&path);
+        env.add_override_str("VARIANT_MEMORY=memory\n");
+        env.set_override("VARIANT_GLOBAL", "global");
+        env.set_thread_override("VARIANT_THREAD", "thread");
+        let source = |name| env.get_with_source(name).map(|(_, source)| source);
+        assert_eq!(source("VARIANT_FILE"), Some(ValueSource::File(path.clone())));
+        assert_eq!(source("VARIANT_MEMORY"), Some(ValueSource::Memory));
+        assert_eq!(source("VARIANT_GLOBAL"), Some(ValueSource::Programmatic));
+        assert_eq!(source("VARIANT_THREAD"), Some(ValueSource::Programmatic));
+        assert_eq!(source("VARIANT_MISSING"), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-process-env"))]
+    fn get_with_source_process_env() {
+        let env = Environment::new();
+        std::env::set_var("VARIANT_PROCESS", "process");
+        let value = env.get_with_source("VARIANT_PROCESS");
+        assert_eq!(value, Some(("process".into(), ValueSource::ProcessEnv)));
+    }
+
+    #[test]
+    fn keep_empty_distinguishes_empty_from_absent() {
+        let env = Environment::new();
+        env.add_override_str("KEEP_EMPTY_KEY=\n");
+        assert_eq!(env.get("KEEP_EMPTY_KEY"), None);
+        assert!(!env.exists("KEEP_EMPTY_KEY"));
+        env.set_keep_empty(true);
+        assert_eq!(env.get("KEEP_EMPTY_KEY").as_deref(), Some(""));
+        assert!(env.exists("KEEP_EMPTY_KEY"));
+        assert_eq!(env.get("KEEP_EMPTY_ABSENT"), None);
+        assert!(!env.exists("KEEP_EMPTY_ABSENT"));
+    }
+
+    #[test]
+    fn prefix_applies_to_file_keys() {
+        let env = Environment::new();
+        env.add_override_str("MYAPP_PREFIX_LEVEL=debug\nPREFIX_BARE=bare\n");
+        env.set_prefix("MYAPP_");
+        // Keys in override files are expected to include the prefix.
+        assert_eq!(env.get("PREFIX_LEVEL").as_deref(), Some("debug"));
+        // Keys without the prefix are not visible through prefixed lookups.
+        assert_eq!(env.get("PREFIX_BARE"), None);
+        env.set_prefix("");
+        assert_eq!(env.get("PREFIX_BARE").as_deref(), Some("bare"));
+        assert_eq!(env.get("MYAPP_PREFIX_LEVEL").as_deref(), Some("debug"));
+    }
+
+    #[test]
+    fn indented_keys_are_trimmed() {
+        let env = Environment::new();
+        let contents = "# [server]\n  INDENTED_HOST = localhost\n\tINDENTED_PORT\t=\t8080\n";
+        let path = write_file("indented.env", contents);
+        env.add_override_path(&path);
+        assert_eq!(env.get("INDENTED_HOST").as_deref(), Some("localhost"));
+        assert_eq!(env.get("INDENTED_PORT").as_deref(), Some("8080"));
+        assert_eq!(env.get("  INDENTED_HOST"), None);
+    }
+
+    #[test]
+    fn include_absolute_path() {
+        let env = Environment::new();
+        let included = write_file("include_abs_child.env", "INCLUDE_ABS=child\n");
+        let contents = format!("@include {}\nINCLUDE_ABS_PARENT=parent\n", included.display());
+        env.add_override_path(write_file("include_abs.env", contents));
+        assert_eq!(env.get("INCLUDE_ABS").as_deref(), Some("child"));
+        assert_eq!(env.get("INCLUDE_ABS_PARENT").as_deref(), Some("parent"));
+    }
+
+    #[test]
+    fn include_relative_path() {
+        let env = Environment::new();
+        let included = write_file("include_rel_child.env", "INCLUDE_REL=child\n");
+        let name = included.file_name().unwrap().to_str().unwrap();
+        env.add_override_path(write_file("include_rel.env", format!("@include {}\n", name)));
+        assert_eq!(env.get("INCLUDE_REL").as_deref(), Some("child"));
+    }
+
+    #[test]
+    fn include_cycle_terminates() {
+        let env = Environment::new();
+        let path = write_file("include_cycle.env", "");
+        let contents = format!("@include {}\nINCLUDE_CYCLE=value\n", path.display());
+        std::fs::write(&path, contents).unwrap();
+        env.add_override_path(&path);
+        assert_eq!(env.get("INCLUDE_CYCLE").as_deref(), Some("value"));
+        assert_eq!(env.get("INCLUDE_CYCLE_MISSING"), None);
+    }
+
+    #[test]
+    fn oversized_files_are_skipped() {
+        let env = Environment::new();
+        let small = write_file("small.env", "SIZE_SMALL=small\n");
+        let large = write_file("large.env", format!("SIZE_LARGE=large\n#{}\n", "x".repeat(1024)));
+        env.add_override_path(&small);
+        env.add_override_path(&large);
+        env.set_max_file_size(Some(64));
+        assert_eq!(env.get("SIZE_SMALL").as_deref(), Some("small"));
+        assert_eq!(env.get("SIZE_LARGE"), None);
+        env.set_max_file_size(None);
+        assert_eq!(env.get("SIZE_LARGE").as_deref(), Some("large"));
+    }
+
+    #[test]
+    fn is_set_and_exists() {
+        let env = Environment::new();
+        env.add_override_str("IS_SET_VALUE=value\nIS_SET_EMPTY=\n");
+        assert!(env.is_set("IS_SET_VALUE"));
+        assert!(env.exists("IS_SET_VALUE"));
+        assert!(env.is_set("IS_SET_EMPTY"));
+        assert!(!env.exists("IS_SET_EMPTY"));
+        assert!(!env.is_set("IS_SET_ABSENT"));
+        assert!(!env.exists("IS_SET_ABSENT"));
+    }
+
+    #[test]
+    fn latin1_files_are_decoded() {
+        let env = Environment::new();
+        let path = write_file("latin1.env", b"LATIN1_CITY=Montr\xE9al\n");
+        env.add_override_path_with_encoding(&path, Encoding::Latin1);
+        assert_eq!(env.get("LATIN1_CITY").as_deref(), Some("Montréal"));
+    }
+
+    #[test]
+    fn get_uncached_reflects_file_changes() {
+        let env = Environment::new();
+        let path = write_file("uncached.env", "UNCACHED_VALUE=old\n");
+        env.add_override_path(&path);
+        assert_eq!(env.get("UNCACHED_VALUE").as_deref(), Some("old"));
+        assert_eq!(env.get_uncached("UNCACHED_VALUE").as_deref(), Some("old"));
+        std::fs::write(&path, "UNCACHED_VALUE=new\n").unwrap();
+        assert_eq!(env.get_uncached("UNCACHED_VALUE").as_deref(), Some("new"));
+        // The cached getters still return the value read first.
+        assert_eq!(env.get("UNCACHED_VALUE").as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn get_prefixed_strips_the_prefix() {
+        let env = Environment::new();
+        env.add_override_str("GPFX_DB_HOST=localhost\nGPFX_DB_PORT=5432\n");
+        env.add_override_str("GPFX_OTHER=x\nDB_HOST=y\n");
+        let values = env.get_prefixed("GPFX_DB_");
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["HOST"], "localhost");
+        assert_eq!(values["PORT"], "5432");
+    }
+
+    #[test]
+    fn missing_trailing_newline() {
+        let env = Environment::new();
+        let path = write_file("no_newline.env", "NO_NEWLINE_A=a\nNO_NEWLINE_LAST=last");
+        env.add_override_path(&path);
+        assert_eq!(env.get("NO_NEWLINE_LAST").as_deref(), Some("last"));
+    }
+
+    #[test]
+    fn trailing_newline_adds_no_entry() {
+        let env = Environment::new();
+        let path = write_file("newline.env", "NEWLINE_A=a\nNEWLINE_LAST=last\n");
+        env.add_override_path(&path);
+        assert_eq!(env.get("NEWLINE_LAST").as_deref(), Some("last"));
+        let entries = crate::file::read_entries(&path, b'=', None).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(env.get(""), None);
+    }
+
+    #[test]
+    fn set_many_sets_every_variable() {
+        let env = Environment::new();
+        env.add_override_str("SET_MANY_C=file\n");
+        env.set_many([
+            ("SET_MANY_A", "a"),
+            ("SET_MANY_B", "b"),
+            ("SET_MANY_C", "c"),
+            ("SET_MANY_EMPTY", ""),
+        ]);
+        assert_eq!(env.get("SET_MANY_A").as_deref(), Some("a"));
+        assert_eq!(env.get("SET_MANY_B").as_deref(), Some("b"));
+        assert_eq!(env.get("SET_MANY_C").as_deref(), Some("c"));
+        assert!(env.is_set("SET_MANY_EMPTY"));
+    }
+
+    #[test]
+    fn cache_size_is_bounded() {
+        let env = Environment::new();
+        env.add_override_str("BOUNDED_HOT=hot\n");
+        env.set_max_cache_entries(Some(4));
+        for i in 0..32 {
+            assert_eq!(env.get(format!("BOUNDED_COLD_{}", i)), None);
+            assert_eq!(env.get("BOUNDED_HOT").as_deref(), Some("hot"));
+            assert!(env.cache_stats().entries <= 4);
+        }
+    }
+
+    #[test]
+    fn deferred_path_resolves_once_created() {
+        let env = Environment::new();
+        let path = write_file("deferred.env", "");
+        std::fs::remove_file(&path).unwrap();
+        assert!(env.add_override_path_deferred(&path));
+        assert!(!env.add_override_path_deferred(&path));
+        assert_eq!(env.get("DEFERRED_VALUE"), None);
+        std::fs::write(&path, "DEFERRED_VALUE=created\n").unwrap();
+        env.clear_cache();
+        assert_eq!(env.get("DEFERRED_VALUE").as_deref(), Some("created"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot add environment override path")]
+    fn deferred_path_rejects_directories() {
+        let env = Environment::new();
+        env.add_override_path_deferred(std::env::temp_dir());
+    }
+
+    #[test]
+    fn thread_overrides_are_isolated() {
+        let env = Environment::new();
+        env.set_override("THREAD_LOCAL_KEY", "global");
+        let barrier = std::sync::Barrier::new(2);
+        let run = |value| {
+            env.set_thread_override("THREAD_LOCAL_KEY", value);
+            // Both threads have set their override before either reads it.
+            barrier.wait();
+            env.get("THREAD_LOCAL_KEY")
+        };
+        std::thread::scope(|s| {
+            let a = s.spawn(|| run("a"));
+            let b = s.spawn(|| run("b"));
+            assert_eq!(a.join().unwrap().as_deref(), Some("a"));
+            assert_eq!(b.join().unwrap().as_deref(), Some("b"));
+        });
+        assert_eq!(env.get("THREAD_LOCAL_KEY").as_deref(), Some("global"));
+        env.set_thread_override("THREAD_LOCAL_KEY", "main");
+        assert_eq!(env.get("THREAD_LOCAL_KEY").as_deref(), Some("main"));
+        env.clear_thread_overrides();
+        assert_eq!(env.get("THREAD_LOCAL_KEY").as_deref(), Some("global"));
+    }
+
+    #[test]
+    #[cfg(feature = "toml")]
+    fn toml_tables_are_flattened() {
+        let env = Environment::new();
+        let contents = "toml_fixture = \"app\"\n\
+            [server]\nport = 8080\n\
+            [server.tls]\nenabled = true\n";
+        let path = write_file("nested.toml", contents);
+        assert!(env.add_toml_override(&path).unwrap());
+        assert_eq!(env.get("TOML_FIXTURE").as_deref(), Some("app"));
+        assert_eq!(env.get("SERVER_PORT").as_deref(), Some("8080"));
+        assert_eq!(env.get("SERVER_TLS_ENABLED").as_deref(), Some("true"));
+    }
+
+    #[test]
+    fn on_change_fires_on_clear_cache() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+        let env = Environment::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        env.on_change(move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+        env.clear_cache();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        env.add_override_str("ON_CHANGE_KEY=value\n");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn frozen_environment_rejects_paths() {
+        let env = Environment::new();
+        let path = write_file("frozen.env", "FROZEN_KEY=value\n");
+        env.freeze();
+        assert!(env.is_frozen());
+        assert!(matches!(env.try_add_override_path(&path), Err(AddPathError::Frozen)));
+        assert!(env.override_paths().is_empty());
+        env.reset();
+        assert!(!env.is_frozen());
+        assert!(env.try_add_override_path(&path).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "frozen")]
+    fn frozen_environment_panics_on_add_override_path() {
+        let env = Environment::new();
+        let path = write_file("frozen_panic.env", "");
+        env.freeze();
+        env.add_override_path(&path);
+    }
+
+    #[test]
+    fn frozen_environment_rejects_override_dir() {
+        let env = Environment::new();
+        env.freeze();
+        let err = env.add_override_dir(std::env::temp_dir()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(env.override_paths().is_empty());
+    }
+
+    #[test]
+    fn cache_stats_counters() {
+        let env = Environment::new();
+        env.add_override_str("STATS_A=a\n");
+        assert_eq!(env.get("STATS_A").as_deref(), Some("a"));
+        assert_eq!(env.get("STATS_A").as_deref(), Some("a"));
+        assert_eq!(env.get("STATS_MISSING"), None);
+        assert_eq!(env.get("STATS_MISSING"), None);
+        env.set_override("STATS_OVERRIDE", "value");
+        let expected = CacheStats {
+            hits: 2,
+            misses: 2,
+            source_reads: 2,
+            entries: 3,
+            negative_entries: 1,
+        };
+        assert_eq!(env.cache_stats(), expected);
+        env.clear_cache();
+        assert_eq!(env.cache_stats().entries, 0);
+    }
+
+    #[test]
+    fn dotenv_syntax() {
+        let env = Environment::new();
+        let contents = "# comment\nDOTENV_QUOTED=\"quoted value\"\n\
+            export DOTENV_EXPORTED=exported # trailing comment\n";
+        let path = write_file("syntax.env", contents);
+        assert!(env.add_dotenv(&path).unwrap());
+        assert!(!env.add_dotenv(&path).unwrap());
+        assert_eq!(env.get("DOTENV_QUOTED").as_deref(), Some("quoted value"));
+        assert_eq!(env.get("DOTENV_EXPORTED").as_deref(), Some("exported"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-process-env"))]
+    fn dotenv_does_not_override_the_process_environment() {
+        let env = Environment::new();
+        std::env::set_var("DOTENV_SHADOWED_KEY", "process");
+        let path = write_file("shadowed.env", "DOTENV_SHADOWED_KEY=file\n");
+        env.add_dotenv(&path).unwrap();
+        assert_eq!(env.get("DOTENV_SHADOWED_KEY").as_deref(), Some("process"));
+        env.set_file_precedence(true);
+        assert_eq!(env.get("DOTENV_SHADOWED_KEY").as_deref(), Some("process"));
+    }
+
+    #[test]
+    fn defaults_file_has_the_lowest_priority() {
+        let env = Environment::new();
+        let defaults = write_file("defaults.env", "DEFAULTS_ONLY=default\nDEFAULTS_BOTH=default\n");
+        let overrides = write_file("defaults_override.env", "DEFAULTS_BOTH=override\n");
+        env.set_defaults_path(&defaults).unwrap();
+        // Paths added after the defaults file are still consulted first.
+        env.add_override_path(&overrides);
+        env.add_override_str("DEFAULTS_MEMORY=memory\n");
+        assert_eq!(env.get("DEFAULTS_ONLY").as_deref(), Some("default"));
+        assert_eq!(env.get("DEFAULTS_BOTH").as_deref(), Some("override"));
+        let source = env.get_with_source("DEFAULTS_ONLY").unwrap().1;
+        assert_eq!(source, ValueSource::File(defaults.clone()));
+        assert_eq!(env.defaults_path(), Some(defaults));
+        assert_eq!(env.override_paths(), vec![overrides]);
+    }
+
+    #[test]
+    fn mtime_revalidation_picks_up_changes() {
+        use std::time::{Duration, SystemTime};
+        let env = Environment::new();
+        let path = write_file("mtime.env", "MTIME_KEY=old\n");
+        let set_mtime = |secs| {
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+        };
+        set_mtime(1_000_000);
+        env.add_override_path(&path);
+        env.set_mtime_revalidation(true);
+        assert_eq!(env.get("MTIME_KEY").as_deref(), Some("old"));
+        // Same size and same modification time: the change is not detected.
+        std::fs::write(&path, "MTIME_KEY=new\n").unwrap();
+        set_mtime(1_000_000);
+        assert_eq!(env.get("MTIME_KEY").as_deref(), Some("old"));
+        set_mtime(2_000_000);
+        assert_eq!(env.get("MTIME_KEY").as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn mtime_revalidation_is_disabled_by_default() {
+        use std::time::{Duration, SystemTime};
+        let env = Environment::new();
+        let path = write_file("mtime_disabled.env", "MTIME_DISABLED=old\n");
+        env.add_override_path(&path);
+        assert_eq!(env.get("MTIME_DISABLED").as_deref(), Some("old"));
+        std::fs::write(&path, "MTIME_DISABLED=new\n").unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        assert_eq!(env.get("MTIME_DISABLED").as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn override_path_count_tracks_paths() {
+        let env = Environment::new();
+        let a = write_file("count_a.env", "");
+        let b = write_file("count_b.env", "");
+        assert_eq!(env.override_path_count(), 0);
+        env.add_override_path(&a);
+        env.add_override_path(&b);
+        env.add_override_path(&a);
+        // In-memory sources and the defaults file are not counted.
+        env.add_override_str("COUNT_MEMORY=1\n");
+        env.set_defaults_path(write_file("count_defaults.env", "")).unwrap();
+        assert_eq!(env.override_path_count(), 2);
+        assert!(env.remove_override_path(&a));
+        assert_eq!(env.override_path_count(), 1);
+    }
+
+    #[test]
+    fn percent_interpolation() {
+        let env = Environment::new();
+        env.add_override_str(
+            "PCT_HOME=C:\\Users\\user\n\
+             PCT_DATA=%PCT_HOME%\\AppData\n\
+             PCT_MISSING=%PCT_UNDEFINED%\\AppData\n\
+             PCT_RATIO=50%%\n"
+        );
+        assert_eq!(env.get("PCT_DATA").as_deref(), Some("%PCT_HOME%\\AppData"));
+        env.set_percent_interpolation(true);
+        assert_eq!(env.get("PCT_DATA").as_deref(), Some("C:\\Users\\user\\AppData"));
+        assert_eq!(env.get("PCT_MISSING").as_deref(), Some("%PCT_UNDEFINED%\\AppData"));
+        assert_eq!(env.get("PCT_RATIO").as_deref(), Some("50%"));
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn percent_interpolation_on_windows() {
+        let env = Environment::new();
+        env.add_override_str("PCT_WIN_ROOT=C:\\Root\nPCT_WIN_DATA=%pct_win_root%\\Data\n");
+        env.set_percent_interpolation(true);
+        // References are case-insensitive, like variable names.
+        assert_eq!(env.get("PCT_WIN_DATA").as_deref(), Some("C:\\Root\\Data"));
+    }
+
+    #[test]
+    #[cfg(all(windows, not(feature = "no-process-env")))]
+    fn percent_interpolation_reads_the_process_environment() {
+        let env = Environment::new();
+        std::env::set_var("PCT_WIN_PROCESS", "C:\\Process");
+        env.add_override_str("PCT_WIN_PATH=%PCT_WIN_PROCESS%\\bin\n");
+        env.set_percent_interpolation(true);
+        assert_eq!(env.get("PCT_WIN_PATH").as_deref(), Some("C:\\Process\\bin"));
+    }
+
+    #[test]
+    fn is_set_distinguishes_empty_from_absent() {
+        let env = Environment::new();
+        env.set_override("IS_SET_OVERRIDE_EMPTY", "");
+        assert!(env.is_set("IS_SET_OVERRIDE_EMPTY"));
+        assert_eq!(env.get_os("IS_SET_OVERRIDE_EMPTY"), None);
+        env.unset_override("IS_SET_OVERRIDE_EMPTY");
+        assert!(!env.is_set("IS_SET_OVERRIDE_EMPTY"));
+        assert!(!env.is_set("IS_SET_OVERRIDE_ABSENT"));
+        assert_eq!(env.get_os("IS_SET_OVERRIDE_ABSENT"), None);
+    }
+
+    #[test]
+    fn add_override_path_accepts_path_types() {
+        let env = Environment::new();
+        let path = write_file("path_types.env", "PATH_TYPES_KEY=value\n");
+        env.add_override_path(path.as_path());
+        env.add_override_path(&path);
+        env.add_override_path(path.clone());
+        let string = path.to_str().unwrap().to_string();
+        env.add_override_path(string.as_str());
+        env.add_override_path(string);
+        assert_eq!(env.override_paths(), vec![path]);
+        assert_eq!(env.get("PATH_TYPES_KEY").as_deref(), Some("value"));
+    }
+
+    #[test]
+    fn overlong_lines_are_skipped_without_a_size_limit() {
+        let env = Environment::new();
+        let mut contents = b"OVERLONG_BEFORE=before\nOVERLONG_LINE=".to_vec();
+        contents.resize(contents.len() + 2 * crate::file::MAX_LINE_LENGTH, b'x');
+        let path = write_file("overlong.env", &contents);
+        env.add_override_path(&path);
+        assert_eq!(env.get("OVERLONG_BEFORE").as_deref(), Some("before"));
+        assert_eq!(env.get("OVERLONG_LINE"), None);
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{Display, Formatter};
+
+/// An error returned when adding an override path.
+#[derive(Debug)]
+pub enum AddPathError {
+    /// The path does not point to a file.
+    NotFile,
+
+    /// An IO error has occurred while reading the metadata of the path.
+    Io(std::io::Error),
+
+    /// The environment is frozen and no longer accepts new override paths.
+    Frozen,
+}
+
+impl Display for AddPathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddPathError::NotFile => f.write_str("path does not point to a file"),
+            AddPathError::Io(e) => write!(f, "io error: {}", e),
+            AddPathError::Frozen => f.write_str("environment is frozen"),
+        }
+    }
+}
+
+impl Error for AddPathError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AddPathError::NotFile | AddPathError::Frozen => None,
+            AddPathError::Io(e) => Some(e),
+        }
+    }
+}
+
+/// An error returned when a required variable is missing.
+#[derive(Debug, Clone)]
+pub struct MissingVarError {
+    name: OsString,
+}
+
+impl MissingVarError {
+    pub(crate) fn new(name: &OsStr) -> MissingVarError {
+        MissingVarError { name: name.into() }
+    }
+
+    /// Returns the name of the missing variable.
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+}
+
+impl Display for MissingVarError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "missing environment variable: {}", self.name.to_string_lossy())
+    }
+}
+
+impl Error for MissingVarError {}
+
+/// An error returned when a boolean variable has an unrecognized value.
+#[derive(Debug, Clone)]
+pub struct ParseBoolError {
+    value: String,
+}
+
+impl ParseBoolError {
+    pub(crate) fn new(value: String) -> ParseBoolError {
+        ParseBoolError { value }
+    }
+
+    /// Returns the unrecognized value, with invalid UTF-8 sequences replaced.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl Display for ParseBoolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unrecognized boolean value: {}", self.value)
+    }
+}
+
+impl Error for ParseBoolError {}
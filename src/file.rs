@@ -0,0 +1,584 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Override file reading utilities.
+
+use crate::environment::Config;
+use crate::parser;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::fmt::{Debug, Formatter};
+use std::io::{BufRead, BufReader, Cursor, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+/// The maximum length of a line of an override file, including the lines joined to it by
+/// continuations.
+pub const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// The maximum size of a file decoded entirely in memory (Latin-1 and UTF-16 files) when no
+/// maximum file size is configured.
+pub const MAX_DECODED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Converts bytes read from an override file to an [OsStr].
+///
+/// On Unix any byte sequence is accepted. Other platforms can only represent valid Unicode in an
+/// [OsStr], so bytes which are not valid UTF-8 are rejected and the entry is skipped: files must
+/// be encoded as UTF-8, UTF-16 with a byte order mark or Latin-1 (see [Encoding]) to be read
+/// there.
+#[cfg(unix)]
+pub fn os_str_from_bytes(data: &[u8]) -> Option<&OsStr> {
+    use std::os::unix::ffi::OsStrExt;
+    //Unix is better because it accepts constructing OsStr from a byte buffer.
+    Some(OsStr::from_bytes(data))
+}
+
+/// Converts bytes read from an override file to an [OsStr].
+///
+/// See the Unix version for details.
+#[cfg(not(unix))]
+pub fn os_str_from_bytes(data: &[u8]) -> Option<&OsStr> {
+    std::str::from_utf8(data).ok().map(OsStr::new)
+}
+
+/// The character encoding of an override file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// UTF-8, or UTF-16 when the file starts with a byte order mark. On Unix, bytes which are not
+    /// valid UTF-8 are passed as is to the environment variable values.
+    #[default]
+    Utf8,
+
+    /// ISO-8859-1 (Latin-1), each byte is decoded to the Unicode code point of the same value.
+    Latin1,
+}
+
+/// A source of override entries.
+pub enum Source {
+    /// An override file on disk, read each time a variable is resolved.
+    File(PathBuf, Encoding),
+
+    /// Override entries loaded in memory.
+    Memory(Box<[u8]>),
+
+    /// A TOML file on disk, flattened to override entries each time a variable is resolved.
+    #[cfg(feature = "toml")]
+    Toml(PathBuf),
+
+    /// A `.env` file on disk, which never takes precedence over the process environment.
+    Dotenv(PathBuf),
+
+    /// The defaults file, always the last source of the list.
+    Defaults(PathBuf),
+}
+
+impl Source {
+    /// Returns the path of this source if it's an override file.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Source::File(path, _) => Some(path),
+            Source::Memory(_) => None,
+            #[cfg(feature = "toml")]
+            Source::Toml(path) => Some(path),
+            Source::Dotenv(path) => Some(path),
+            Source::Defaults(path) => Some(path),
+        }
+    }
+
+    /// Returns true if this source is a `.env` file.
+    pub fn is_dotenv(&self) -> bool {
+        matches!(self, Source::Dotenv(_))
+    }
+
+    /// Returns true if this source is the defaults file.
+    pub fn is_defaults(&self) -> bool {
+        matches!(self, Source::Defaults(_))
+    }
+
+    /// Opens this source for reading.
+    pub fn open(&self, config: &Config) -> std::io::Result<Box<dyn BufRead + '_>> {
+        match self {
+            Source::File(path, encoding) => open_file(path, config.max_file_size, *encoding),
+            Source::Memory(data) => decode(&data[..], u64::MAX),
+            #[cfg(feature = "toml")]
+            Source::Toml(path) => crate::toml_file::open(path, config),
+            Source::Dotenv(path) | Source::Defaults(path) => {
+                open_file(path, config.max_file_size, Encoding::Utf8)
+            }
+        }
+    }
+}
+
+impl Debug for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::File(path, _) => path.fmt(f),
+            Source::Memory(_) => f.write_str("<memory>"),
+            #[cfg(feature = "toml")]
+            Source::Toml(path) => path.fmt(f),
+            Source::Dotenv(path) | Source::Defaults(path) => path.fmt(f),
+        }
+    }
+}
+
+/// Opens an override file for reading.
+///
+/// Files which are not regular files, or which are larger than max_size bytes, are rejected
+/// before being opened. At most max_size bytes are read even if the file grows after being
+/// opened. The length of each line is bounded separately by [lines].
+///
+/// Latin-1 files are entirely decoded to UTF-8, see [decode] for details about UTF-8 files. Files
+/// decoded entirely are limited to max_size bytes, or to [MAX_DECODED_SIZE] bytes if max_size is
+/// None.
+pub fn open_file(
+    path: &Path,
+    max_size: Option<u64>,
+    encoding: Encoding
+) -> std::io::Result<Box<dyn BufRead>> {
+    let metadata = std::fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Err(Error::new(ErrorKind::InvalidInput, "not a regular file"));
+    }
+    if let Some(max_size) = max_size {
+        if metadata.len() > max_size {
+            warn!(?path, size = metadata.len(), max_size, "skipping oversized override file");
+            return Err(Error::new(ErrorKind::InvalidData, "file too large"));
+        }
+    }
+    let file = File::open(path)?.take(max_size.unwrap_or(u64::MAX));
+    let limit = max_size.unwrap_or(MAX_DECODED_SIZE);
+    match encoding {
+        Encoding::Utf8 => decode(BufReader::new(file), limit),
+        Encoding::Latin1 => {
+            let data = read_limited(file, limit)?;
+            let text: String = data.into_iter().map(char::from).collect();
+            Ok(Box::new(Cursor::new(text.into_bytes())))
+        }
+    }
+}
+
+/// Reads all the remaining data of a reader, failing if it's larger than limit bytes.
+fn read_limited(mut reader: impl Read, limit: u64) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.by_ref().take(limit.saturating_add(1)).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        warn!(limit, "skipping override file too large to be decoded");
+        return Err(Error::new(ErrorKind::InvalidData, "file too large to be decoded"));
+    }
+    Ok(data)
+}
+
+/// Prepares override entries for reading.
+///
+/// A leading UTF-8 byte order mark is skipped. Data starting with a UTF-16 byte order mark is
+/// entirely decoded to UTF-8 before being returned, in which case an error is returned if the
+/// data is larger than limit bytes.
+pub fn decode<'a>(
+    mut file: impl BufRead + 'a,
+    limit: u64
+) -> std::io::Result<Box<dyn BufRead + 'a>> {
+    let big_endian = match file.fill_buf()? {
+        [0xFF, 0xFE, ..] => false,
+        [0xFE, 0xFF, ..] => true,
+        [0xEF, 0xBB, 0xBF, ..] => {
+            file.consume(3);
+            return Ok(Box::new(file));
+        }
+        _ => return Ok(Box::new(file)),
+    };
+    file.consume(2);
+    let data = read_limited(file, limit)?;
+    let units = data.chunks_exact(2).map(|v| {
+        if big_endian {
+            u16::from_be_bytes([v[0], v[1]])
+        } else {
+            u16::from_le_bytes([v[0], v[1]])
+        }
+    });
+    let text = char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(Box::new(Cursor::new(text.into_bytes())))
+}
+
+/// Iterates over the lines of an override file, without line endings.
+///
+/// The last line is returned even if the file does not end with a line ending, and a trailing
+/// line ending does not produce an additional empty line.
+///
+/// Physical lines ending with a backslash continuation are joined together (see
+/// [parser::strip_continuation]). Each item is returned together with the 1-based number of its
+/// first physical line.
+///
+/// An error is returned, and the iteration stops, if a line is longer than [MAX_LINE_LENGTH]
+/// bytes, so that a file without line endings can't exhaust memory.
+pub fn lines(mut file: impl BufRead) -> impl Iterator<Item = std::io::Result<(usize, Vec<u8>)>> {
+    let mut number = 0;
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let res = read_logical_line(&mut file, &mut number).transpose();
+        done = !matches!(res, Some(Ok(_)));
+        res
+    })
+}
+
+/// Reads a physical line without its line ending, or None at the end of the file.
+fn read_line(file: &mut impl BufRead) -> std::io::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    // Leave room for a CRLF line ending after a line of the maximum length.
+    let limit = MAX_LINE_LENGTH as u64 + 2;
+    if file.take(limit).read_until(b'\n', &mut data)? == 0 {
+        return Ok(None);
+    }
+    if data.last() == Some(&b'\n') {
+        data.pop();
+    }
+    // Support files written with CRLF line endings.
+    if data.last() == Some(&b'\r') {
+        data.pop();
+    }
+    if data.len() > MAX_LINE_LENGTH {
+        return Err(line_too_long());
+    }
+    Ok(Some(data))
+}
+
+/// Reads a line and the lines joined to it by continuations, counting physical lines in number.
+fn read_logical_line(
+    file: &mut impl BufRead,
+    number: &mut usize
+) -> std::io::Result<Option<(usize, Vec<u8>)>> {
+    let mut data = match read_line(file)? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    *number += 1;
+    let start = *number;
+    while parser::strip_continuation(&mut data) {
+        match read_line(file)? {
+            Some(v) => data.extend_from_slice(&v),
+            None => break,
+        }
+        if data.len() > MAX_LINE_LENGTH {
+            return Err(line_too_long());
+        }
+        *number += 1;
+    }
+    Ok(Some((start, data)))
+}
+
+/// Returns the error reported for lines longer than [MAX_LINE_LENGTH].
+fn line_too_long() -> Error {
+    warn!(max = MAX_LINE_LENGTH, "skipping the rest of an override file with an overlong line");
+    Error::new(ErrorKind::InvalidData, "line too long")
+}
+
+/// Reads the entries of a single override file, in file order.
+///
+/// Directives are ignored and lines which can't be represented on this platform are skipped. Files
+/// larger than max_size are reported as an error.
+pub fn read_entries(
+    path: &Path,
+    delimiter: u8,
+    max_size: Option<u64>
+) -> std::io::Result<Vec<(OsString, OsString)>> {
+    let mut entries = Vec::new();
+    for v in lines(open_file(path, max_size, Encoding::Utf8)?) {
+        let (_, data) = v?;
+        if parser::parse_include(&data).is_some() || parser::parse_unset(&data).is_some() {
+            continue;
+        }
+        let (key, value) = match parser::split_line(&data, delimiter) {
+            Some(v) => v,
+            None => continue,
+        };
+        let value = parser::parse_value(value);
+        if let (Some(key), Some(value)) = (os_str_from_bytes(key), os_str_from_bytes(&value)) {
+            entries.push((key.into(), value.into()));
+        }
+    }
+    Ok(entries)
+}
+
+/// A callback receiving the key and raw value of each entry, and None at the end of each file.
+///
+/// The value is None for keys unset with an `@unset` directive.
+type Visitor<'a, T> = dyn FnMut(Option<(&[u8], Option<&[u8]>)>) -> Option<T> + 'a;
+
+/// Reads the entries of an override file, then the entries of the files it includes.
+///
+/// f is called with each entry and with None at the end of each file, until it returns Some.
+/// Relative include paths are resolved against dir, or against the current directory if dir is
+/// None. Included files are decoded using the given encoding. Files listed in visited are
+/// skipped, which guards against include cycles.
+fn scan<T>(
+    file: Box<dyn BufRead + '_>,
+    dir: Option<&Path>,
+    encoding: Encoding,
+    config: &Config,
+    visited: &mut Vec<PathBuf>,
+    f: &mut Visitor<'_, T>
+) -> Option<T> {
+    let mut includes = Vec::new();
+    for v in lines(file) {
+        let data = match v {
+            Ok((_, v)) => v,
+            Err(_) => break, // If an IO error has occurred, skip loading the file
+                             // completely.
+        };
+        if let Some(path) = parser::parse_include(&data) {
+            if let Some(path) = os_str_from_bytes(path) {
+                includes.push(dir.map(|v| v.join(path)).unwrap_or_else(|| path.into()));
+            }
+            continue;
+        }
+        let (key, value) = match parser::parse_unset(&data) {
+            Some(key) => (key, None),
+            None => match parser::split_line(&data, config.delimiter) {
+                Some((key, value)) => (key, Some(value)),
+                None => continue,
+            },
+        };
+        if let Some(v) = f(Some((key, value))) {
+            return Some(v);
+        }
+    }
+    if let Some(v) = f(None) {
+        return Some(v);
+    }
+    // Included entries have a lower precedence than the entries of the including file.
+    for path in includes {
+        let id = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if visited.contains(&id) {
+            continue;
+        }
+        visited.push(id);
+        let file = match open_file(&path, config.max_file_size, encoding) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if let Some(v) = scan(file, path.parent(), encoding, config, visited, f) {
+            return Some(v);
+        }
+    }
+    None
+}
+
+/// Reads an override source and the files it includes, calling f with None at the end of each
+/// file.
+fn scan_source<T>(
+    source: &Source,
+    config: &Config,
+    f: &mut Visitor<'_, T>
+) -> Option<T> {
+    let mut visited = Vec::new();
+    let path = source.path();
+    if let Some(path) = path {
+        visited.push(path.canonicalize().unwrap_or_else(|_| path.into()));
+    }
+    let encoding = match source {
+        Source::File(_, encoding) => *encoding,
+        _ => Encoding::Utf8,
+    };
+    let file = source.open(config).ok()?;
+    scan(file, path.and_then(Path::parent), encoding, config, &mut visited, f)
+}
+
+/// Reads an override source line by line, calling f with the key and raw value of each entry
+/// until it returns Some. The value is None for keys unset with an `@unset` directive.
+///
+/// The entries of included files are read after the entries of the including file.
+pub fn scan_file<T>(
+    source: &Source,
+    config: &Config,
+    mut f: impl FnMut(&[u8], Option<&[u8]>) -> Option<T>
+) -> Option<T> {
+    scan_source(source, config, &mut |entry| {
+        let (key, value) = entry?;
+        f(key, value)
+    })
+}
+
+/// Reads an override source and returns the raw value of the last entry whose key matches.
+///
+/// Only the first file, in include order, containing a matching entry is considered. Some(None)
+/// is returned if the last matching entry is an `@unset` directive.
+pub fn scan_file_last(
+    source: &Source,
+    config: &Config,
+    mut matches: impl FnMut(&[u8]) -> bool
+) -> Option<Option<Vec<u8>>> {
+    let mut last = None;
+    scan_source(source, config, &mut |entry| match entry {
+        Some((key, value)) => {
+            if matches(key) {
+                last = Some(value.map(<[u8]>::to_vec));
+            }
+            None
+        }
+        None => last.take(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects the logical lines of data.
+    fn collect(data: &[u8]) -> Vec<(usize, Vec<u8>)> {
+        lines(Cursor::new(data)).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn lines_single_continuation() {
+        let lines = collect(b"KEY=part1\\\npart2\nOTHER=1\n");
+        assert_eq!(lines, [(1, b"KEY=part1part2".to_vec()), (3, b"OTHER=1".to_vec())]);
+    }
+
+    #[test]
+    fn lines_multiple_continuations() {
+        let lines = collect(b"KEY=a\\\r\nb\\\nc\nOTHER=1");
+        assert_eq!(lines, [(1, b"KEY=abc".to_vec()), (4, b"OTHER=1".to_vec())]);
+    }
+
+    #[test]
+    fn lines_escaped_backslash() {
+        let lines = collect(b"A=x\\\\\nB=y\n");
+        assert_eq!(lines, [(1, b"A=x\\\\".to_vec()), (2, b"B=y".to_vec())]);
+        assert_eq!(parser::parse_value(b"\"x\\\\\""), b"x\\");
+    }
+
+    #[test]
+    fn lines_escaped_backslash_then_continuation() {
+        let lines = collect(b"A=x\\\\\\\nB=y\n");
+        assert_eq!(lines, [(1, b"A=x\\\\B=y".to_vec())]);
+    }
+
+    #[test]
+    fn lines_continuation_at_end_of_file() {
+        assert_eq!(collect(b"A=x\\"), [(1, b"A=x".to_vec())]);
+    }
+
+    /// Encodes text as UTF-16 with a byte order mark.
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        for v in std::iter::once(0xFEFF).chain(text.encode_utf16()) {
+            match big_endian {
+                true => data.extend_from_slice(&v.to_be_bytes()),
+                false => data.extend_from_slice(&v.to_le_bytes()),
+            }
+        }
+        data
+    }
+
+    /// Decodes data and collects its logical lines.
+    fn decode_lines(data: &[u8]) -> Vec<(usize, Vec<u8>)> {
+        lines(decode(Cursor::new(data), u64::MAX).unwrap()).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn decode_utf16_le() {
+        let lines = decode_lines(&utf16("KEY=välue\r\nOTHER=1\r\n", false));
+        assert_eq!(lines, [(1, "KEY=välue".into()), (2, b"OTHER=1".to_vec())]);
+    }
+
+    #[test]
+    fn decode_utf16_be() {
+        let lines = decode_lines(&utf16("KEY=välue\n", true));
+        assert_eq!(lines, [(1, "KEY=välue".into())]);
+    }
+
+    #[test]
+    fn decode_utf8_with_and_without_bom() {
+        assert_eq!(decode_lines("\u{FEFF}KEY=välue\n".as_bytes()), [(1, "KEY=välue".into())]);
+        assert_eq!(decode_lines("KEY=välue\n".as_bytes()), [(1, "KEY=välue".into())]);
+    }
+
+    #[test]
+    fn decode_invalid_utf16() {
+        // An unpaired surrogate.
+        let data = [0xFF, 0xFE, 0x00, 0xD8, b'A', 0x00];
+        assert!(decode(Cursor::new(&data[..]), u64::MAX).is_err());
+    }
+
+    #[test]
+    fn read_entries_cases() {
+        let name = format!("bp3d_env_{}_read_entries.env", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        let contents = "NORMAL=value\nEMPTY=\nMISSING_DELIMITER\nDUP=1\n@unset DUP\nDUP=2\n";
+        std::fs::write(&path, contents).unwrap();
+        let entries = read_entries(&path, b'=', None).unwrap();
+        let expected: [(OsString, OsString); 4] = [
+            ("NORMAL".into(), "value".into()),
+            ("EMPTY".into(), "".into()),
+            ("DUP".into(), "1".into()),
+            ("DUP".into(), "2".into()),
+        ];
+        assert_eq!(entries, expected);
+        assert!(read_entries(&path, b'=', Some(8)).is_err());
+        let size = contents.len() as u64;
+        assert_eq!(read_entries(&path, b'=', Some(size)).unwrap(), expected);
+    }
+
+    #[test]
+    fn lines_rejects_overlong_lines() {
+        let mut data = b"A=1\nB=".to_vec();
+        data.resize(data.len() + MAX_LINE_LENGTH, b'x');
+        let mut iter = lines(Cursor::new(&data[..]));
+        assert_eq!(iter.next().unwrap().unwrap(), (1, b"A=1".to_vec()));
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn lines_rejects_overlong_joined_lines() {
+        let part = format!("{}\\\n", "x".repeat(MAX_LINE_LENGTH / 2));
+        let data = format!("A={}{}{}end\n", part, part, part);
+        let mut iter = lines(Cursor::new(data.as_bytes()));
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn lines_accepts_lines_at_the_limit() {
+        let mut data = vec![b'x'; MAX_LINE_LENGTH];
+        data.extend_from_slice(b"\r\nB=1");
+        let lines = collect(&data);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].1.len(), MAX_LINE_LENGTH);
+    }
+
+    #[test]
+    fn decode_rejects_oversized_utf16() {
+        let data = utf16("KEY=value\n", false);
+        assert!(decode(Cursor::new(&data[..]), 8).is_err());
+        assert!(decode(Cursor::new(&data[..]), data.len() as u64).is_ok());
+    }
+}
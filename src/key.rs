@@ -0,0 +1,183 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Variable name comparison.
+
+use std::borrow::Borrow;
+use std::ffi::{OsStr, OsString};
+use std::hash::{Hash, Hasher};
+
+/// Returns true if two variable names are equal.
+///
+/// Names are compared case-insensitively on Windows, matching the semantics of the native
+/// environment, and case-sensitively on other platforms.
+#[cfg(windows)]
+pub fn keys_equal(a: &OsStr, b: &OsStr) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+/// Returns true if two variable names are equal.
+///
+/// Names are compared case-insensitively on Windows, matching the semantics of the native
+/// environment, and case-sensitively on other platforms.
+#[cfg(not(windows))]
+pub fn keys_equal(a: &OsStr, b: &OsStr) -> bool {
+    a == b
+}
+
+/// A borrowed variable name, hashed and compared according to [keys_equal].
+///
+/// This is the borrowed form of [Key], which lets maps keyed by [Key] be searched without
+/// allocating an owned name.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct KeyRef(OsStr);
+
+impl KeyRef {
+    /// Wraps a variable name.
+    pub fn new(name: &OsStr) -> &KeyRef {
+        // SAFETY: KeyRef is a transparent wrapper around OsStr, so both references share the same
+        // layout and metadata.
+        unsafe { &*(name as *const OsStr as *const KeyRef) }
+    }
+}
+
+impl PartialEq for KeyRef {
+    fn eq(&self, other: &Self) -> bool {
+        keys_equal(&self.0, &other.0)
+    }
+}
+
+impl Eq for KeyRef {}
+
+impl Hash for KeyRef {
+    #[cfg(windows)]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use std::os::windows::ffi::OsStrExt;
+        for v in self.0.encode_wide() {
+            match u8::try_from(v) {
+                Ok(v) => state.write_u16(v.to_ascii_uppercase() as u16),
+                Err(_) => state.write_u16(v),
+            }
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+/// A variable name used as a map key, hashed and compared according to [keys_equal].
+#[derive(Debug, Clone, Default)]
+pub struct Key(OsString);
+
+impl Key {
+    /// Returns the name as it was originally written.
+    pub fn as_os_str(&self) -> &OsStr {
+        &self.0
+    }
+
+    /// Returns the name as it was originally written.
+    pub fn into_os_string(self) -> OsString {
+        self.0
+    }
+}
+
+impl From<&OsStr> for Key {
+    fn from(value: &OsStr) -> Self {
+        Key(value.into())
+    }
+}
+
+impl From<OsString> for Key {
+    fn from(value: OsString) -> Self {
+        Key(value)
+    }
+}
+
+impl Borrow<KeyRef> for Key {
+    fn borrow(&self) -> &KeyRef {
+        KeyRef::new(&self.0)
+    }
+}
+
+// Key must hash and compare exactly like KeyRef for Borrow lookups to find entries.
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        let this: &KeyRef = self.borrow();
+        this == other.borrow()
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let this: &KeyRef = self.borrow();
+        this.hash(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    #[cfg(windows)]
+    fn keys_are_case_insensitive() {
+        assert!(keys_equal(OsStr::new("Path"), OsStr::new("PATH")));
+        assert!(keys_equal(OsStr::new("path"), OsStr::new("PATH")));
+        let mut map = HashMap::new();
+        map.insert(Key::from(OsStr::new("path")), 1);
+        assert_eq!(map.get(&Key::from(OsStr::new("PATH"))), Some(&1));
+        assert_eq!(map.get(&Key::from(OsStr::new("Path"))), Some(&1));
+        assert_eq!(map.get(KeyRef::new(OsStr::new("pAtH"))), Some(&1));
+        map.insert(Key::from(OsStr::new("PATH")), 2);
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn keys_are_case_sensitive() {
+        assert!(!keys_equal(OsStr::new("Path"), OsStr::new("PATH")));
+        let mut map = HashMap::new();
+        map.insert(Key::from(OsStr::new("path")), 1);
+        assert_eq!(map.get(&Key::from(OsStr::new("PATH"))), None);
+        assert_eq!(map.get(KeyRef::new(OsStr::new("path"))), Some(&1));
+        assert_eq!(map.get(KeyRef::new(OsStr::new("PATH"))), None);
+    }
+
+    #[test]
+    fn key_keeps_original_name() {
+        let key = Key::from(OsStr::new("Path"));
+        assert_eq!(key.as_os_str(), "Path");
+        assert_eq!(key.into_os_string(), "Path");
+    }
+}
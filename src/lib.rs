@@ -29,19 +29,75 @@
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::str::FromStr;
+use std::time::Duration;
 
-static PATHS: Lazy<Mutex<Vec<PathBuf>>> = Lazy::new(|| Mutex::new(Vec::new()));
-static ENV_CACHE: Lazy<Mutex<HashMap<OsString, Option<OsString>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($arg: tt)*) => { tracing::debug!($($arg)*) };
+}
 
-/// Adds a new override path.
+#[cfg(not(feature = "tracing"))]
+macro_rules! debug {
+    ($($arg: tt)*) => {};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! trace {
+    ($($arg: tt)*) => { tracing::trace!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace {
+    ($($arg: tt)*) => {};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! warn {
+    ($($arg: tt)*) => { tracing::warn!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! warn {
+    ($($arg: tt)*) => {};
+}
+
+#[cfg(feature = "serde")]
+mod de;
+mod environment;
+mod error;
+mod file;
+mod key;
+mod level;
+mod override_file;
+mod parser;
+#[cfg(feature = "toml")]
+mod toml_file;
+mod validate;
+#[cfg(feature = "watch")]
+mod watch;
+
+#[cfg(feature = "serde")]
+pub use de::EnvError;
+pub use environment::{CacheStats, DuplicatePolicy, Environment, OverrideGuard, ValueSource};
+pub use error::{AddPathError, MissingVarError, ParseBoolError};
+pub use file::Encoding;
+pub use level::LogLevel;
+pub use override_file::OverrideFile;
+pub use validate::{ParseWarning, WarningReason};
+#[cfg(feature = "watch")]
+pub use watch::WatchHandle;
+
+static ENV: Lazy<Environment> = Lazy::new(Environment::new);
+
+/// Attempts to add a new override path.
 ///
-/// If the path is already added, nothing happens. If the path is not already present, the
-/// requested path is cloned and inserted in the global path list.
+/// If the path is already added, nothing happens and false is returned. If the path is not
+/// already present, the requested path is cloned and inserted in the global path list and true is
+/// returned, which lets plugin loaders detect accidental double registrations.
 ///
 /// Additionally, when a new path is added, the function invalidates the cache to let a chance
 /// to the getters to read the new override.
@@ -50,121 +106,730 @@ static ENV_CACHE: Lazy<Mutex<HashMap<OsString, Option<OsString>>>> =
 ///
 /// This is best called when initializing the application.
 ///
+/// # Errors
+///
+/// Returns an [AddPathError] if the path does not point to a file, if its metadata could not be
+/// read (for example when the file does not exist) or if the environment is frozen (see
+/// [freeze]).
+pub fn try_add_override_path<P: AsRef<Path>>(path: P) -> Result<bool, AddPathError> {
+    ENV.try_add_override_path(path)
+}
+
+/// Adds a new override path.
+///
+/// This is the panicking version of [try_add_override_path]. Unlike [try_add_override_path], a
+/// path which does not exist is accepted: it's skipped by the getters until the file is created.
+///
 /// # Panics
 ///
-/// The function panics if the path does not point to a file.
-pub fn add_override_path(path: &Path) {
-    if path.is_dir() || path.is_symlink() {
-        panic!("Cannot add non-file environment override path!");
-    }
-    let mut lock = PATHS.lock().unwrap();
-    if lock.iter().any(|p| p == path) {
-        return;
-    }
-    lock.push(path.into());
-    let mut lock1 = ENV_CACHE.lock().unwrap();
-    lock1.clear();
-}
-
-fn check_insert_key_value(
-    cache: &mut HashMap<OsString, Option<OsString>>,
-    searched: impl AsRef<OsStr>,
-    key: impl AsRef<OsStr>,
-    value: impl AsRef<OsStr>
-) -> Option<Option<OsString>> {
-    if key.as_ref() == searched.as_ref() {
-        if value.as_ref().is_empty() {
-            cache.insert(key.as_ref().into(), None);
-        } else {
-            cache.insert(key.as_ref().into(), Some(value.as_ref().into()));
-        }
-        Some(cache[key.as_ref()].clone())
-    } else {
-        None
-    }
+/// The function panics if the path points to a directory or a symlink, or if the environment is
+/// frozen (see [freeze]).
+pub fn add_override_path<P: AsRef<Path>>(path: P) {
+    ENV.add_override_path(path)
 }
 
-#[cfg(windows)]
-fn windows_path(cache: &mut HashMap<OsString, Option<OsString>>, searched: impl AsRef<OsStr>, data: &[u8], pos: usize) -> Option<Option<OsString>> {
-    let key = match std::str::from_utf8(&data[..pos]) {
-        Ok(v) => v,
-        Err(_) => return None,
-    };
-    let value = match std::str::from_utf8(&data[pos + 1..]) {
-        Ok(v) => v,
-        Err(_) => return None,
-    };
-    check_insert_key_value(cache, searched, key, value)
+/// Adds a new override path which may not exist yet, reporting duplicate registrations.
+///
+/// This works like [add_override_path], which also accepts a path that does not exist yet (for
+/// example when the file is written later by another step of the startup sequence), except that
+/// it returns true if the path was added and false if it was already added, in which case nothing
+/// happens. Until the file exists it's skipped by the getters. Once the file is created, call
+/// [clear_cache] (or use `watch` with the `watch` feature) to let a chance to the getters to read
+/// it, as variables resolved in the mean time are cached.
+///
+/// **Note: This is a slow function with allocations, locks and linear search.**
+///
+/// # Panics
+///
+/// The function panics if the path points to a directory or a symlink, or if the environment is
+/// frozen (see [freeze]).
+pub fn add_override_path_deferred<P: AsRef<Path>>(path: P) -> bool {
+    ENV.add_override_path_deferred(path)
 }
 
-/// Gets the content of an environment variable.
+/// Attempts to add a new override path decoded with the given encoding.
 ///
-/// Returns None if the variable does not exist.
+/// This behaves like [try_add_override_path] except that the file is decoded using the given
+/// [Encoding] instead of UTF-8; files included by this file are decoded the same way. If the path
+/// is already added, only its encoding is updated and false is returned.
 ///
-/// **Note: for optimization reasons, the functions caches values.**
+/// **Note: This is a slow function with allocations, locks and linear search.**
 ///
-/// The cost of this function is amortized O(1) (thanks to the cache). Once a value is loaded it's
-/// cached to avoid re-loading it. When a value is not loaded the cost of this function is O(nm)
-/// with n the number of items in the override path list and m the number of lines in each override
+/// # Errors
+///
+/// Returns an [AddPathError] if the path does not point to a file, if its metadata could not be
+/// read (for example when the file does not exist) or if the environment is frozen (see
+/// [freeze]).
+pub fn try_add_override_path_with_encoding<P: AsRef<Path>>(
+    path: P,
+    encoding: Encoding
+) -> Result<bool, AddPathError> {
+    ENV.try_add_override_path_with_encoding(path, encoding)
+}
+
+/// Adds a new override path decoded with the given encoding.
+///
+/// This is the panicking version of [try_add_override_path_with_encoding]. Like
+/// [add_override_path], a path which does not exist is accepted.
+///
+/// # Panics
+///
+/// The function panics if the path points to a directory or a symlink, or if the environment is
+/// frozen (see [freeze]).
+pub fn add_override_path_with_encoding<P: AsRef<Path>>(path: P, encoding: Encoding) {
+    ENV.add_override_path_with_encoding(path, encoding)
+}
+
+/// Adds a new override path pointing to a TOML file.
+///
+/// The file is appended to the override path list, like with [try_add_override_path], and is
+/// read each time a variable is resolved. Nested tables are flattened by joining keys with `_`
+/// and converting them to upper case, so `port = 8080` in the `[server]` table is read as
+/// `SERVER_PORT`. Values are converted as follows:
+///
+/// - strings are used as is, numbers, booleans and dates are converted to their TOML text;
+/// - arrays of such values are joined with commas, for use with [get_list];
+/// - arrays containing tables or other arrays are skipped;
+/// - keys containing whitespace or the delimiter (see [set_delimiter]) are skipped.
+///
+/// Files which are not valid TOML are skipped as if they could not be read. Returns true if the
+/// path was added and false if it was already added, in which case nothing happens.
+///
+/// **Note: this function is only available with the `toml` feature.**
+///
+/// # Errors
+///
+/// Returns an [AddPathError] if the path does not point to a file, if its metadata could not be
+/// read (for example when the file does not exist) or if the environment is frozen (see
+/// [freeze]).
+#[cfg(feature = "toml")]
+pub fn add_toml_override<P: AsRef<Path>>(path: P) -> Result<bool, AddPathError> {
+    ENV.add_toml_override(path)
+}
+
+/// Adds a new override path pointing to a `.env` file, following the rules of the `dotenv` crate.
+///
+/// The file is appended to the override path list, like with [try_add_override_path], and uses
+/// the same format: a leading `export ` is ignored, values may be quoted and `#` starts a
+/// comment. Unlike other override files, variables already set in the process environment are
+/// never overridden by a `.env` file, even when [set_file_precedence] is enabled. The file is
+/// always read as UTF-8.
+///
+/// Returns true if the path was added and false if it was already added, in which case nothing
+/// happens.
+///
+/// # Errors
+///
+/// Returns an [AddPathError] if the path does not point to a file, if its metadata could not be
+/// read (for example when the file does not exist) or if the environment is frozen (see
+/// [freeze]).
+///
+/// # Examples
+///
+#[cfg_attr(not(feature = "no-process-env"), doc = "```")]
+#[cfg_attr(feature = "no-process-env", doc = "```ignore")]
+/// let path = std::env::temp_dir().join("bp3d_env_add_dotenv.env");
+/// let contents = "# comment\n\
+///     QUOTED=\"quoted value\"\n\
+///     export EXPORTED=exported # trailing comment\n\
+///     DOTENV_SHADOWED=file\n";
+/// std::fs::write(&path, contents).unwrap();
+/// std::env::set_var("DOTENV_SHADOWED", "process");
+/// bp3d_env::add_dotenv(&path).unwrap();
+/// bp3d_env::set_file_precedence(true);
+/// assert_eq!(bp3d_env::get("QUOTED").as_deref(), Some("quoted value"));
+/// assert_eq!(bp3d_env::get("EXPORTED").as_deref(), Some("exported"));
+/// assert_eq!(bp3d_env::get("DOTENV_SHADOWED").as_deref(), Some("process"));
+/// ```
+pub fn add_dotenv<P: AsRef<Path>>(path: P) -> Result<bool, AddPathError> {
+    ENV.add_dotenv(path)
+}
+
+/// Sets the defaults file, consulted after every other override source.
+///
+/// The defaults file is kept separately from the override path list: it's always the last source
+/// read, whatever the paths added before or after it, and it's not affected by
+/// [add_override_path_front] or [remove_override_path]. Calling this function again replaces the
+/// previous defaults file. The cache is invalidated.
+///
+/// Variables are resolved in the following order, the first value found wins:
+///
+/// 1. thread-local and programmatic overrides (see [set_thread_override] and [set_override]);
+/// 2. when [set_file_precedence] is disabled (the default), the process environment;
+/// 3. the override sources, in the order of the override path list;
+/// 4. the defaults file;
+/// 5. when [set_file_precedence] is enabled, the process environment, then the files added with
+///    [add_dotenv].
+///
+/// An `@unset` directive in an override source also hides the variable from the defaults file.
+///
+/// # Errors
+///
+/// Returns an [AddPathError] if the path does not point to a file, if its metadata could not be
+/// read (for example when the file does not exist) or if the environment is frozen (see
+/// [freeze]).
+///
+/// # Examples
+///
+/// ```
+/// let defaults = std::env::temp_dir().join("bp3d_env_defaults.env");
+/// let overrides = std::env::temp_dir().join("bp3d_env_defaults_override.env");
+/// std::fs::write(&defaults, "DEFAULTS_ONLY=default\nDEFAULTS_BOTH=default\n").unwrap();
+/// std::fs::write(&overrides, "DEFAULTS_BOTH=override\n").unwrap();
+/// bp3d_env::set_defaults_path(&defaults).unwrap();
+/// bp3d_env::add_override_path(&overrides);
+/// assert_eq!(bp3d_env::get("DEFAULTS_ONLY").as_deref(), Some("default"));
+/// assert_eq!(bp3d_env::get("DEFAULTS_BOTH").as_deref(), Some("override"));
+/// ```
+pub fn set_defaults_path<P: AsRef<Path>>(path: P) -> Result<(), AddPathError> {
+    ENV.set_defaults_path(path)
+}
+
+/// Adds every `*.env` file of a directory as override paths.
+///
+/// The directory is not searched recursively and entries which are not regular files are
+/// skipped. The files are appended to the back of the override path list sorted by file name
+/// (byte-wise), so earlier names take precedence: with `10-local.env` and `20-base.env`, a
+/// variable defined in both files is read from `10-local.env`. Files which are already registered
+/// keep their current position.
+///
+/// Returns the number of paths added. When at least one path is added, the function invalidates
+/// the cache to let a chance to the getters to read the new overrides.
+///
+/// **Note: This is a slow function with allocations, locks and linear search.**
+///
+/// # Errors
+///
+/// Returns an error if the directory could not be read, in which case no path is added, or an
+/// error of kind [PermissionDenied](std::io::ErrorKind::PermissionDenied) if the environment is
+/// frozen (see [freeze]).
+pub fn add_override_dir<P: AsRef<Path>>(dir: P) -> std::io::Result<usize> {
+    ENV.add_override_dir(dir)
+}
+
+/// Adds a new override path with the highest priority.
+///
+/// On a cache miss, override files are scanned front-to-back and the first file defining the
+/// requested variable wins. Files added with [add_override_path] are appended to the back of the
+/// list while this function inserts the path at the front, making it the highest-priority override
+/// file. If the path is already added, it's moved to the front of the list.
+///
+/// Additionally, when the list changes, the function invalidates the cache to let a chance to the
+/// getters to read the new override.
+///
+/// **Note: This is a slow function with allocations, locks and linear search.**
+///
+/// # Panics
+///
+/// The function panics if the path points to a directory or a symlink, or if the environment is
+/// frozen (see [freeze]).
+pub fn add_override_path_front<P: AsRef<Path>>(path: P) {
+    ENV.add_override_path_front(path)
+}
+
+/// Removes an override path.
+///
+/// Returns true if the path was previously registered and has been removed, false otherwise.
+///
+/// When a path is removed, the function invalidates the cache to drop any value which was loaded
+/// from the removed override file.
+///
+/// **Note: This is a slow function with locks and linear search.**
+///
+/// This is best called outside of hot paths.
+pub fn remove_override_path<P: AsRef<Path>>(path: P) -> bool {
+    ENV.remove_override_path(path)
+}
+
+/// Returns a copy of the list of registered override paths.
+///
+/// The paths are returned in priority order, which is the order in which override files are
+/// scanned on a cache miss: the first file defining a variable wins. Override entries added with
+/// [add_override_str] or [add_override_reader] and the defaults file (see [set_defaults_path])
+/// are not included.
+///
+/// This is mostly useful to log the active configuration sources.
+pub fn override_paths() -> Vec<PathBuf> {
+    ENV.override_paths()
+}
+
+/// Returns the number of registered override paths.
+///
+/// This is the length of the list returned by [override_paths], without cloning it.
+///
+/// # Examples
+///
+/// ```
+/// let a = std::env::temp_dir().join("bp3d_env_count_a.env");
+/// let b = std::env::temp_dir().join("bp3d_env_count_b.env");
+/// std::fs::write(&a, "").unwrap();
+/// std::fs::write(&b, "").unwrap();
+/// assert!(!bp3d_env::has_override_paths());
+/// bp3d_env::add_override_path(&a);
+/// bp3d_env::add_override_path(&b);
+/// bp3d_env::add_override_path(&a);
+/// assert_eq!(bp3d_env::override_path_count(), 2);
+/// assert!(bp3d_env::has_override_paths());
+/// ```
+pub fn override_path_count() -> usize {
+    ENV.override_path_count()
+}
+
+/// Returns true if at least one override path is registered.
+///
+/// See [override_path_count] for details.
+pub fn has_override_paths() -> bool {
+    override_path_count() > 0
+}
+
+/// Re-reads a single override file on the next access to any variable it may define.
+///
+/// Unlike [clear_cache], this only invalidates the cached values which may be affected by a change
+/// to the given file: values loaded from this file or from lower-priority override sources, as
+/// well as variables cached as missing. Values from higher-priority override files are kept, and
+/// so are values from the process environment unless [set_file_precedence] is enabled. When
+/// interpolation is enabled, every value loaded from an override source is invalidated as it may
+/// reference a variable defined in the reloaded file. Programmatic overrides are always kept.
+///
+/// Nothing happens if the path is not registered.
+///
+/// **Note: to support this function, each cache entry records where its value comes from, which
+/// slightly increases the memory footprint of the cache. This function is linear in the number
+/// of cached values.**
+pub fn reload_path<P: AsRef<Path>>(path: P) {
+    ENV.reload_path(path)
+}
+
+/// Adds override entries from a string.
+///
+/// The string uses the same format as override files. The entries are appended to the back of
+/// the override list, after any override file already registered, and are consulted in that
+/// order on a cache miss; like override files, they are only consulted after the process
+/// environment unless [set_file_precedence] is enabled. In-memory entries are kept for the
+/// lifetime of the program: they survive [clear_cache] and cannot be removed.
+///
+/// Additionally, the function invalidates the cache to let a chance to the getters to read the
+/// new entries.
+///
+/// **Note: This is a slow function with allocations and locks.**
+pub fn add_override_str(contents: &str) {
+    ENV.add_override_str(contents)
+}
+
+/// Adds override entries read from a reader.
+///
+/// The reader is read to the end immediately and the entries are used exactly like the ones added
+/// by [add_override_str].
+///
+/// **Note: This is a slow function with allocations and locks.**
+///
+/// # Errors
+///
+/// Returns an error if the reader could not be read, in which case no entries are added.
+pub fn add_override_reader<R: Read>(reader: R) -> std::io::Result<()> {
+    ENV.add_override_reader(reader)
+}
+
+/// Clears the value cache.
+///
+/// The next call to any getter will re-resolve the requested variable: values from the process
+/// environment (`std::env`) are re-cached on next access and override files are lazily re-parsed.
+///
+/// This is mostly useful in tests or to pick up changes made to override files on disk.
+pub fn clear_cache() {
+    ENV.clear_cache()
+}
+
+/// Freezes the override source list and the programmatic overrides.
+///
+/// This enforces that the configuration sources are only set up while initializing the
+/// application. Once frozen, [try_add_override_path] and [try_add_override_path_with_encoding]
+/// return [AddPathError::Frozen] and [add_override_dir] returns an error of kind
+/// [PermissionDenied](std::io::ErrorKind::PermissionDenied), while the functions which would
+/// change the override source list or the programmatic overrides panic: this includes
+/// [add_override_path], [add_override_path_front], [remove_override_path], [add_override_str],
+/// [set_override], [unset_override], [set_many] and [scoped_override].
+///
+/// Reading variables, invalidating the cache (for example with [clear_cache] or when override
+/// files change on disk), changing settings and thread-local overrides are still allowed. The
+/// crate can only be unfrozen by [reset].
+pub fn freeze() {
+    ENV.freeze()
+}
+
+/// Returns true if the crate has been frozen with [freeze].
+pub fn is_frozen() -> bool {
+    ENV.is_frozen()
+}
+
+/// Registers a callback called whenever the cache is invalidated.
+///
+/// The callback is called after the cache has been cleared by [clear_cache], by a change to the
+/// override source list (such as [add_override_path] or [remove_override_path]), by
+/// [reload_path], by [reset], by a change to a setting, or by the hot-reload watcher of the
+/// `watch` feature. Callbacks are called in registration order on the thread which invalidated
+/// the cache. They can't be unregistered and are kept by [reset].
+///
+/// **Note: callbacks may read variables but must not invalidate the cache nor register another
+/// callback, which would dead-lock.**
+pub fn on_change(callback: impl Fn() + Send + 'static) {
+    ENV.on_change(callback)
+}
+
+/// Resets the crate to its initial state.
+///
+/// This removes every override path and in-memory override source, clears the cache (including
+/// programmatic overrides), restores every setting to its default value and unfreezes the crate
+/// (see [freeze]). The thread-local overrides of the calling thread are removed as well. This is
+/// primarily useful for test isolation.
+///
+/// The override path list, the cache and the settings are locked together, in the same order as
+/// the getters lock them, so that concurrent getters never observe a partially reset state.
+pub fn reset() {
+    ENV.reset()
+}
+
+/// Watches all override files and invalidates the cache whenever any of them changes on disk.
+///
+/// Only the override paths registered at the time of the call are watched; files pulled in with
+/// an `@include` directive are not. Rapid successive changes, such as the ones produced by a
+/// single editor save, are debounced into a single invalidation. Getters running concurrently
+/// with an invalidation simply re-read the override files lazily.
+///
+/// Dropping the returned [WatchHandle] stops watching.
+///
+/// **Note: this function is only available with the `watch` feature.**
+///
+/// # Errors
+///
+/// Returns an error if the watcher could not be created or if a directory containing an override
+/// file could not be watched.
+#[cfg(feature = "watch")]
+pub fn watch() -> notify::Result<WatchHandle> {
+    ENV.watch()
+}
+
+/// Overrides the value of a variable.
+///
+/// The value is stored directly in the cache and therefore takes precedence over both the process
+/// environment and override files: subsequent getters return it without touching the disk. As with
+/// any other source, an empty value is returned as None by the getters but is reported as defined
+/// by [is_set].
+///
+/// **Note: programmatic overrides live in the cache, which means they are wiped by [clear_cache]
+/// and by any function which invalidates the cache such as [add_override_path].**
+pub fn set_override<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, value: V) {
+    ENV.set_override(key, value)
+}
+
+/// Overrides the values of several variables at once.
+///
+/// This works like calling [set_override] for each entry, except that the cache lock is taken
+/// only once: concurrent readers either see none or all of the new values. When the same variable
+/// appears several times, the last entry wins.
+///
+/// **Note: programmatic overrides live in the cache, which means they are wiped by [clear_cache]
+/// and by any function which invalidates the cache such as [add_override_path].**
+pub fn set_many<I, K, V>(entries: I)
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>
+{
+    ENV.set_many(entries)
+}
+
+/// Overrides a variable to be unset.
+///
+/// Subsequent getters return None for this variable regardless of the process environment and
+/// override files.
+///
+/// **Note: programmatic overrides live in the cache, which means they are wiped by [clear_cache]
+/// and by any function which invalidates the cache such as [add_override_path].**
+pub fn unset_override<K: AsRef<OsStr>>(key: K) {
+    ENV.unset_override(key)
+}
+
+/// Overrides the value of a variable for the current thread only.
+///
+/// Thread-local overrides are consulted before the cache by every getter called from the same
+/// thread, so they take precedence over all other sources including [set_override]. Other threads
+/// are not affected and the cache is left untouched. When no thread-local override exists for a
+/// variable, getters behave as usual.
+///
+/// Unlike [set_override], thread-local overrides are not wiped when the cache is invalidated;
+/// they're removed by [clear_thread_overrides] or [reset]. [snapshot] and the functions based on
+/// it ignore thread-local overrides.
+pub fn set_thread_override<K: AsRef<OsStr>, V: AsRef<OsStr>>(key: K, value: V) {
+    ENV.set_thread_override(key, value)
+}
+
+/// Removes all the thread-local overrides of the current thread.
+///
+/// See [set_thread_override] for details.
+pub fn clear_thread_overrides() {
+    ENV.clear_thread_overrides()
+}
+
+/// Overrides the value of a variable until the returned guard is dropped.
+///
+/// This works like [set_override] but the returned [OverrideGuard] restores the previous
+/// programmatic override of the variable when dropped, even on panic. This is mostly useful in
+/// tests.
+///
+/// **Note: values resolved from a source are never restored: if the variable was not overridden,
+/// its cache entry is removed when the guard is dropped and the next lookup reads the sources
+/// again. A previous programmatic override is restored even if the cache was invalidated while
+/// the guard was alive.**
+pub fn scoped_override<K: AsRef<OsStr>, V: AsRef<OsStr>>(
+    key: K,
+    value: V
+) -> OverrideGuard<'static> {
+    ENV.scoped_override(key, value)
+}
+
+/// Enables or disables variable interpolation in override files.
+///
+/// When enabled, `${NAME}` references in values read from override files are substituted by the
+/// value of `NAME` resolved through the normal lookup precedence. Unresolved references expand to
+/// an empty string and self-referencing cycles are left unexpanded. A literal `$` can be written
+/// as `$$`.
+///
+/// Interpolation is disabled by default. Changing this setting invalidates the cache.
+pub fn set_interpolation(enabled: bool) {
+    ENV.set_interpolation(enabled)
+}
+
+/// Enables or disables Windows style `%NAME%` variable interpolation in override files.
+///
+/// When enabled, `%NAME%` references in values read from override files, such as
+/// `%USERPROFILE%\AppData`, are substituted by the value of `NAME` resolved through the normal
+/// lookup precedence. Unlike `${NAME}` references (see [set_interpolation]), unresolved references
+/// are left as is, matching the behavior of the Windows command prompt. Self-referencing cycles
+/// are left unexpanded and a literal `%` can be written as `%%`. Both syntaxes can be enabled at
+/// the same time.
+///
+/// Percent interpolation is disabled by default on every platform. Changing this setting
+/// invalidates the cache.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::add_override_str(
+///     "PERCENT_HOME=C:\\Users\\user\n\
+///      PERCENT_DATA=%PERCENT_HOME%\\AppData\n\
+///      PERCENT_MISSING=%PERCENT_UNDEFINED%\\AppData\n\
+///      PERCENT_RATIO=50%%\n"
+/// );
+/// bp3d_env::set_percent_interpolation(true);
+/// assert_eq!(bp3d_env::get("PERCENT_DATA").as_deref(), Some("C:\\Users\\user\\AppData"));
+/// assert_eq!(
+///     bp3d_env::get("PERCENT_MISSING").as_deref(),
+///     Some("%PERCENT_UNDEFINED%\\AppData")
+/// );
+/// assert_eq!(bp3d_env::get("PERCENT_RATIO").as_deref(), Some("50%"));
+/// ```
+pub fn set_percent_interpolation(enabled: bool) {
+    ENV.set_percent_interpolation(enabled)
+}
+
+/// Sets whether override files take precedence over the process environment.
+///
+/// By default, a variable defined in the process environment shadows any definition in override
+/// files. When enabled, override files are consulted first and the process environment is only
+/// used as a fallback, which is useful when the override files should be authoritative (for
+/// example in containerized deployments).
+///
+/// Changing this setting invalidates the cache.
+pub fn set_file_precedence(enabled: bool) {
+    ENV.set_file_precedence(enabled)
+}
+
+/// Sets the byte separating keys from values in override files.
+///
+/// The default delimiter is `=`. This is useful to consume files produced by other tools, for
+/// example `KEY: value` files with `:` as delimiter. Lines are always split at the first
+/// occurrence of the delimiter.
+///
+/// Changing this setting invalidates the cache.
+pub fn set_delimiter(delimiter: u8) {
+    ENV.set_delimiter(delimiter)
+}
+
+/// Sets a prefix prepended to every variable name passed to the getters.
+///
+/// For example, after `set_prefix("MYAPP_")`, `get("LOG_LEVEL")` resolves `MYAPP_LOG_LEVEL`. The
+/// prefix applies uniformly to [get_os], [get], [get_bool], [is_set], [get_with_source] and every
+/// getter built on them. The prefixed name is resolved from every source, which means keys in
+/// override files are expected to include the prefix (`MYAPP_LOG_LEVEL=debug`), exactly like in
+/// the process environment.
+///
+/// Functions which do not look up a single variable, such as [set_override], [snapshot] and
+/// [keys], always use full variable names. An empty prefix, the default, disables prefixing.
+pub fn set_prefix<T: AsRef<OsStr>>(prefix: T) {
+    ENV.set_prefix(prefix)
+}
+
+/// Sets which definition of a key is used when it's defined several times in the same override
 /// file.
-pub fn get_os<T: AsRef<OsStr>>(name: T) -> Option<OsString> {
-    let mut cache = ENV_CACHE.lock().unwrap();
-    {
-        // Attempt to pull from the cache.
-        if let Some(val) = cache.get(name.as_ref()) {
-            return val.clone();
-        }
-    }
-    {
-        // Value is not in cache, try pulling from environment variables.
-        if let Some(val) = std::env::var_os(name.as_ref()) {
-            cache.insert(name.as_ref().into(), Some(val.clone()));
-            return Some(val.clone());
-        }
-    }
-    {
-        // Value is still not in cache, try pulling from the override file list.
-        let lock = PATHS.lock().unwrap();
-        for v in &*lock {
-            let file = match File::open(v) {
-                Ok(v) => BufReader::new(v),
-                Err(_) => continue,
-            };
-            for v in file.split(b'\n') {
-                let data = match v {
-                    Ok(v) => v,
-                    Err(_) => break, // If an IO error has occurred, skip loading the file
-                                     // completely.
-                };
-                let pos = match data.iter().position(|v| *v == b'=') {
-                    Some(v) => v,
-                    None => continue,
-                };
-                #[cfg(windows)]
-                if let Some(res) = windows_path(&mut cache, name.as_ref(), &data, pos) {
-                    return res;
-                }
-                #[cfg(unix)]
-                {
-                    use std::os::unix::ffi::OsStrExt;
-                    //Unix is better because it accepts constructing OsStr from a byte buffer.
-                    let key = OsStr::from_bytes(&data[..pos]);
-                    let value = OsStr::from_bytes(&data[pos + 1..]);
-                    if let Some(res) = check_insert_key_value(&mut cache, name.as_ref(), key, value) {
-                        return res;
-                    }
-                }
-            }
-        }
-    }
-    // Everything failed; just place a None in the cache and assume the variable does not exist.
-    cache.insert(name.as_ref().into(), None);
-    None
+///
+/// With the default [DuplicatePolicy::FirstWins], the first definition in a file is used and the
+/// scan of the file stops there. With [DuplicatePolicy::LastWins], the whole file is scanned and
+/// the last definition is used, matching the behavior of sourcing the file in a shell. This only
+/// applies within a single file: across files, the first file defining a variable always wins.
+///
+/// Changing this setting invalidates the cache.
+pub fn set_duplicate_policy(policy: DuplicatePolicy) {
+    ENV.set_duplicate_policy(policy)
+}
+
+/// Sets the maximum size in bytes of the override files which are read.
+///
+/// Override files, including files pulled in with an `@include` directive, which are larger than
+/// the limit are skipped as if they did not exist, and a warning is emitted when the `tracing`
+/// feature is enabled. The size is checked before the file is opened and no more than the limit
+/// is ever read, even if the file grows in the mean time. Independently of this setting, paths
+/// which do not point to a regular file, such as named pipes, are always skipped. [validate_path]
+/// and [parse_file] report oversized files as an IO error.
+///
+/// Memory use is bounded even without a limit: a line, including the lines joined to it by
+/// continuations, can't be longer than 64 KiB and the rest of a file containing a longer line is
+/// skipped. Latin-1 and UTF-16 files, which are decoded in memory, are skipped when larger than
+/// the limit, or than 16 MiB when no limit is set.
+///
+/// The default is None: override files are read regardless of their size. Changing this setting
+/// invalidates the cache.
+pub fn set_max_file_size(max_size: Option<u64>) {
+    ENV.set_max_file_size(max_size)
+}
+
+/// Sets the maximum number of entries in the cache.
+///
+/// Every resolved variable is cached, including variables which were not found, so looking up
+/// many distinct names makes the cache grow indefinitely. When a limit is set and the cache is
+/// full, all resolved entries are evicted before a new one is inserted; evicted variables are
+/// simply resolved again on their next lookup. Programmatic overrides (see [set_override]) are
+/// never evicted but count towards the limit.
+///
+/// The default is None: the cache is unbounded. Changing this setting invalidates the cache.
+pub fn set_max_cache_entries(max: Option<usize>) {
+    ENV.set_max_cache_entries(max)
+}
+
+/// Sets whether variables with an empty value are returned as present-empty by the getters.
+///
+/// By default, an empty value, such as `KEY=` in an override file, is treated as an unset
+/// variable and [get_os] returns None for it. When enabled, every empty value is returned as
+/// present-empty instead, for compatibility with dotenv: `KEY=` resolves to `Some("")` while an
+/// absent key still resolves to None. This applies to every source, including the process
+/// environment, as well as to [snapshot] and the getters built on [get_os].
+///
+/// Changing this setting invalidates the cache.
+pub fn set_keep_empty(enabled: bool) {
+    ENV.set_keep_empty(enabled)
+}
+
+/// Sets whether the getters check the modification time of override files.
+///
+/// When enabled, every lookup going through the cache (such as [get_os], [get] and the typed
+/// getters) first reads the modification time of each override file and of the defaults file.
+/// If a file changed since the previous lookup, the cached values which may have been read from
+/// it are invalidated, like with [reload_path], and are read again. This keeps values fresh
+/// without the background thread of `watch`.
+///
+/// Files included with `@include` are not checked. Changes are only detected when the
+/// modification time changes, which may have a coarse resolution on some file systems.
+///
+/// **Note: this costs one `stat` system call per override file on every lookup, including cache
+/// hits, and serializes lookups on the override path list lock.**
+///
+/// Disabled by default. Changing this setting invalidates the cache.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// let path = std::env::temp_dir().join("bp3d_env_mtime.env");
+/// std::fs::write(&path, "MTIME_VALUE=old\n").unwrap();
+/// bp3d_env::add_override_path(&path);
+/// bp3d_env::set_mtime_revalidation(true);
+/// assert_eq!(bp3d_env::get("MTIME_VALUE").as_deref(), Some("old"));
+/// std::fs::write(&path, "MTIME_VALUE=new\n").unwrap();
+/// // Make sure the modification time advances even on file systems with a coarse resolution.
+/// let file = std::fs::File::options().write(true).open(&path).unwrap();
+/// file.set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+/// assert_eq!(bp3d_env::get("MTIME_VALUE").as_deref(), Some("new"));
+/// ```
+pub fn set_mtime_revalidation(enabled: bool) {
+    ENV.set_mtime_revalidation(enabled)
+}
+
+/// Sets for how long a variable which was not found in any source is remembered as missing.
+///
+/// By default (None), a missing variable is cached as missing until the cache is invalidated, so
+/// a variable written to an override file later is not seen without calling [clear_cache]. With a
+/// TTL, a missing variable looked up after the TTL has elapsed is re-resolved from every source.
+/// Variables unset with [unset_override] never expire.
+///
+/// Changing this setting invalidates the cache.
+pub fn set_negative_ttl(ttl: Option<Duration>) {
+    ENV.set_negative_ttl(ttl)
+}
+
+/// Validates an override file.
+///
+/// The file is parsed the same way as when resolving variables and a warning is returned for
+/// every line which is ignored or shadowed. This does not change the lenient behavior of the
+/// getters, but is useful to lint configuration files during CI or at startup.
+///
+/// On Windows, variable names and values must be valid Unicode: lines which are not valid UTF-8
+/// once decoded are skipped by the getters and reported with
+/// [InvalidEncoding](WarningReason::InvalidEncoding). Such lines never produce a warning on Unix,
+/// where raw bytes are passed as is to the values.
+///
+/// # Errors
+///
+/// Returns an IO error if the file could not be read.
+pub fn validate_path<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<ParseWarning>> {
+    ENV.validate_path(path)
+}
+
+/// Parses the entries of an override file, in file order.
+///
+/// The lines are parsed like when resolving a variable, using the default `=` delimiter: keys
+/// and values are trimmed, comments are removed and quoted values are unquoted. Values are not
+/// interpolated. Lines without a delimiter or with an empty key are skipped, as well as lines which
+/// are not valid on this platform (see [validate_path] to report them). `@include` and `@unset`
+/// directives are not followed.
+///
+/// Unlike the getters, empty values are returned as is and every definition of a duplicate key
+/// is returned. Apart from the maximum file size (see [set_max_file_size]), this function doesn't
+/// use the global state of this crate.
+///
+/// # Errors
+///
+/// Returns an error if the file could not be read or if it's larger than the maximum file size.
+///
+/// # Examples
+///
+/// ```
+/// let path = std::env::temp_dir().join("bp3d_env_parse_file.env");
+/// std::fs::write(&path, "# comment\nNAME = value\nEMPTY=\nINVALID\n").unwrap();
+/// let entries = bp3d_env::parse_file(&path).unwrap();
+/// assert_eq!(entries, [("NAME".into(), "value".into()), ("EMPTY".into(), "".into())]);
+/// ```
+pub fn parse_file<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<(OsString, OsString)>> {
+    file::read_entries(path.as_ref(), b'=', ENV.config().max_file_size)
 }
 
 /// Gets the content of an environment variable.
 ///
-/// Returns None if the variable does not exist or is not valid UTF-8.
+/// Returns None if the variable does not exist or is empty. Use [is_set] to distinguish a defined
+/// but empty variable from an absent one, or enable [set_keep_empty] to have empty values
+/// returned as is.
+///
+/// When the `no-process-env` feature is enabled, the process environment is never consulted and
+/// values are exclusively resolved from override files and programmatic overrides.
 ///
 /// **Note: for optimization reasons, the functions caches values.**
 ///
@@ -172,24 +837,1172 @@ pub fn get_os<T: AsRef<OsStr>>(name: T) -> Option<OsString> {
 /// cached to avoid re-loading it. When a value is not loaded the cost of this function is O(nm)
 /// with n the number of items in the override path list and m the number of lines in each override
 /// file.
-pub fn get<T: AsRef<OsStr>>(name: T) -> Option<String> {
-    get_os(name).and_then(|v| v.into_string().ok())
+pub fn get_os<T: AsRef<OsStr>>(name: T) -> Option<OsString> {
+    ENV.get_os(name)
 }
 
-/// Gets a boolean environment variable.
+/// Passes the content of an environment variable to f without cloning it.
 ///
-/// Returns None if the variable does not exist or the format is unrecognized.
+/// f receives the same value as [get_os] would return, borrowed directly from the cache. This
+/// avoids an allocation for variables which are read very frequently.
 ///
-/// **Note: for optimization reasons, the functions caches values.**
+/// **Note: f runs while the cache is locked, it must not call any function of this crate or it
+/// may dead-lock.**
 ///
-/// The cost of this function is amortized O(1) (thanks to the cache). Once a value is loaded it's
-/// cached to avoid re-loading it. When a value is not loaded the cost of this function is O(nm)
-/// with n the number of items in the override path list and m the number of lines in each override
+/// The cost of this function is the same as [get_os].
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("WITH_VALUE_MODE", "fast");
+/// let fast = bp3d_env::with_value("WITH_VALUE_MODE", |v| v == Some("fast".as_ref()));
+/// assert!(fast);
+/// ```
+pub fn with_value<T: AsRef<OsStr>, R>(name: T, f: impl FnOnce(Option<&OsStr>) -> R) -> R {
+    ENV.with_value(name, f)
+}
+
+/// Gets the content of an environment variable or a default value.
+///
+/// Returns default if the variable does not exist or is empty. The default is only copied when
+/// it's used.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("GET_OS_OR_FOUND", "value");
+/// assert_eq!(bp3d_env::get_os_or("GET_OS_OR_FOUND", "default"), "value");
+/// assert_eq!(bp3d_env::get_os_or("GET_OS_OR_MISSING", "default"), "default");
+/// ```
+pub fn get_os_or<T: AsRef<OsStr>, D: AsRef<OsStr>>(name: T, default: D) -> OsString {
+    get_os(name).unwrap_or_else(|| default.as_ref().into())
+}
+
+/// Gets the content of an environment variable or computes a default value.
+///
+/// Returns the result of f if the variable does not exist or is empty; f is not called otherwise.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("GET_OS_OR_ELSE_FOUND", "value");
+/// let value = bp3d_env::get_os_or_else("GET_OS_OR_ELSE_FOUND", || unreachable!());
+/// assert_eq!(value, "value");
+/// let value = bp3d_env::get_os_or_else("GET_OS_OR_ELSE_MISSING", || "default".into());
+/// assert_eq!(value, "default");
+/// ```
+pub fn get_os_or_else<T: AsRef<OsStr>, F: FnOnce() -> OsString>(name: T, f: F) -> OsString {
+    get_os(name).unwrap_or_else(f)
+}
+
+/// Gets the content of the first of several environment variables which is defined.
+///
+/// Each name is looked up in order with [get_os] and the first non-empty value is returned. This
+/// is mostly useful to rename a variable while still accepting its previous names. Each lookup
+/// goes through the cache, so repeated calls do not re-read the override files.
+///
+/// When the `tracing` feature is enabled, a warning is emitted whenever the value is resolved
+/// from any name other than the first one, which helps tracking deployments still using a
+/// deprecated name.
+pub fn get_any_os<T: AsRef<OsStr>>(names: &[T]) -> Option<OsString> {
+    names.iter().enumerate().find_map(|(i, name)| {
+        let value = get_os(name)?;
+        if i > 0 {
+            warn!(
+                name = ?name.as_ref(),
+                preferred = ?names[0].as_ref(),
+                "variable resolved from a fallback name"
+            );
+        }
+        Some(value)
+    })
+}
+
+/// Gets the content of a required environment variable.
+///
+/// An empty value is treated as missing, consistent with [get_os].
+///
+/// # Errors
+///
+/// Returns a [MissingVarError] naming the variable if it does not exist or is empty.
+pub fn require_os<T: AsRef<OsStr>>(name: T) -> Result<OsString, MissingVarError> {
+    get_os(name.as_ref()).ok_or_else(|| MissingVarError::new(name.as_ref()))
+}
+
+/// Gets the content of an environment variable together with where it comes from.
+///
+/// Returns None if the variable does not exist, is empty or is not valid UTF-8.
+///
+/// This is mostly useful for diagnostics. The source of a value is remembered in the cache, so
+/// repeated calls do not re-read the override files.
+///
+/// **Note: unlike the other getters, this function always takes the cache write lock.**
+pub fn get_with_source<T: AsRef<OsStr>>(name: T) -> Option<(String, ValueSource)> {
+    ENV.get_with_source(name)
+}
+
+/// Returns true if the variable is defined in any source, even if its value is empty.
+///
+/// Empty values, such as a `KEY=` line in an override file, are treated as unset by all getters
+/// but are reported as defined by this function.
+///
+/// **Note: for optimization reasons, the functions caches values.**
+///
+/// The cost of this function is the same as [get_os].
+pub fn is_set<T: AsRef<OsStr>>(name: T) -> bool {
+    ENV.is_set(name)
+}
+
+/// Returns true if [get_os] would return a value for this variable.
+///
+/// The value is resolved like [get_os] but is never cloned out of the cache, which makes this
+/// function suitable for feature detection. Present but empty variables, such as a `KEY=` line
+/// in an override file, do not exist unless empty values are kept (see [set_keep_empty]); use
+/// [is_set] to also detect them.
+///
+/// **Note: for optimization reasons, the functions caches values.**
+///
+/// The cost of this function is the same as [get_os].
+pub fn exists<T: AsRef<OsStr>>(name: T) -> bool {
+    ENV.exists(name)
+}
+
+/// Gets the content of an environment variable.
+///
+/// Returns None if the variable does not exist or is not valid UTF-8.
+///
+/// **Note: for optimization reasons, the functions caches values.**
+///
+/// The cost of this function is amortized O(1) (thanks to the cache). Once a value is loaded it's
+/// cached to avoid re-loading it. When a value is not loaded the cost of this function is O(nm)
+/// with n the number of items in the override path list and m the number of lines in each override
+/// file.
+pub fn get<T: AsRef<OsStr>>(name: T) -> Option<String> {
+    ENV.get(name)
+}
+
+/// Gets the content of an environment variable and transforms it with f.
+///
+/// Returns None if the variable does not exist or is not valid UTF-8 (see [get]), or if f
+/// returns None. Unlike [with_value], f receives an owned copy of the value and may call other
+/// functions of this crate.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("HOME", "/home/user");
+/// bp3d_env::set_override("GET_WITH_DATA_DIR", "~/data");
+/// let dir = bp3d_env::get_with("GET_WITH_DATA_DIR", |v| match v.strip_prefix('~') {
+///     Some(rest) => Some(bp3d_env::get("HOME")? + rest),
+///     None => Some(v)
+/// });
+/// assert_eq!(dir.as_deref(), Some("/home/user/data"));
+/// ```
+pub fn get_with<T: AsRef<OsStr>, R>(name: T, f: impl FnOnce(String) -> Option<R>) -> Option<R> {
+    get(name).and_then(f)
+}
+
+/// Gets the content of an environment variable or a default value.
+///
+/// Returns default if the variable does not exist, is empty or is not valid UTF-8. The default is
+/// only copied when it's used.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("GET_OR_FOUND", "value");
+/// assert_eq!(bp3d_env::get_or("GET_OR_FOUND", "default"), "value");
+/// assert_eq!(bp3d_env::get_or("GET_OR_MISSING", "default"), "default");
+/// ```
+pub fn get_or<T: AsRef<OsStr>>(name: T, default: &str) -> String {
+    get(name).unwrap_or_else(|| default.into())
+}
+
+/// Gets the content of an environment variable or computes a default value.
+///
+/// Returns the result of f if the variable does not exist, is empty or is not valid UTF-8; f is
+/// not called otherwise.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("GET_OR_ELSE_FOUND", "value");
+/// let value = bp3d_env::get_or_else("GET_OR_ELSE_FOUND", || unreachable!());
+/// assert_eq!(value, "value");
+/// let value = bp3d_env::get_or_else("GET_OR_ELSE_MISSING", || "default".into());
+/// assert_eq!(value, "default");
+/// ```
+pub fn get_or_else<T: AsRef<OsStr>, F: FnOnce() -> String>(name: T, f: F) -> String {
+    get(name).unwrap_or_else(f)
+}
+
+/// Looks up an environment variable with an optional default value.
+///
+/// The one-argument form expands to [get] and returns an `Option<String>`; the two-argument form
+/// expands to [get_or] and returns a `String`.
+///
+/// **Note: despite the syntax, this is a runtime lookup going through the cache of this crate and
+/// not a compile-time lookup like [env!].**
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("ENV_OR_FOUND", "value");
+/// assert_eq!(bp3d_env::env_or!("ENV_OR_FOUND"), Some("value".into()));
+/// assert_eq!(bp3d_env::env_or!("ENV_OR_MISSING"), None);
+/// assert_eq!(bp3d_env::env_or!("ENV_OR_FOUND", "default"), "value");
+/// assert_eq!(bp3d_env::env_or!("ENV_OR_MISSING", "default"), "default");
+/// ```
+#[macro_export]
+macro_rules! env_or {
+    ($name: expr) => {
+        $crate::get($name)
+    };
+    ($name: expr, $default: expr) => {
+        $crate::get_or($name, $default)
+    };
+}
+
+/// Gets the content of the first of several environment variables which is defined.
+///
+/// Returns None if none of the variables is defined or if the first defined one is not valid
+/// UTF-8. See [get_any_os] for details.
+pub fn get_any<T: AsRef<OsStr>>(names: &[T]) -> Option<String> {
+    get_any_os(names).and_then(|v| v.into_string().ok())
+}
+
+/// Gets the trimmed content of an environment variable.
+///
+/// Returns None if the variable does not exist, is not valid UTF-8 or contains only whitespace.
+/// Otherwise, the value is returned without its leading and trailing whitespace.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_nonempty<T: AsRef<OsStr>>(name: T) -> Option<String> {
+    let value = get(name)?;
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        None
+    } else if trimmed.len() == value.len() {
+        Some(value)
+    } else {
+        Some(trimmed.into())
+    }
+}
+
+/// Gets the content of a required environment variable.
+///
+/// An empty value is treated as missing, consistent with [get].
+///
+/// # Errors
+///
+/// Returns a [MissingVarError] naming the variable if it does not exist, is empty or is not valid
+/// UTF-8.
+pub fn require<T: AsRef<OsStr>>(name: T) -> Result<String, MissingVarError> {
+    get(name.as_ref()).ok_or_else(|| MissingVarError::new(name.as_ref()))
+}
+
+/// Gets the content of an environment variable, bypassing the cache.
+///
+/// Returns None if the variable does not exist, is empty or is not valid UTF-8.
+///
+/// The variable is always resolved again from the process environment and the override sources,
+/// which is useful for variables expected to change while the application is running. The cache
+/// is neither consulted nor updated, except for programmatic overrides (see [set_override])
+/// which still take precedence. Variables referenced through interpolation are resolved again as
+/// well.
+///
+/// **Note: This is a slow function with allocations, locks and linear search.**
+///
+/// The cost of this function is always O(nm) with n the number of items in the override path
+/// list and m the number of lines in each override file.
+pub fn get_uncached<T: AsRef<OsStr>>(name: T) -> Option<String> {
+    ENV.get_uncached(name)
+}
+
+/// Gets a boolean environment variable.
+///
+/// Returns None if the variable does not exist or the format is unrecognized.
+///
+/// Recognized values are `on`, `true`, `yes`, `y` and `1` for true and `off`, `false`, `no`, `n`
+/// and `0` for false. Values are matched case-insensitively, for example `True` and `oFF` are
+/// accepted.
+///
+/// **Note: for optimization reasons, the functions caches values.**
+///
+/// The cost of this function is amortized O(1) (thanks to the cache). Once a value is loaded it's
+/// cached to avoid re-loading it. When a value is not loaded the cost of this function is O(nm)
+/// with n the number of items in the override path list and m the number of lines in each override
 /// file.
 pub fn get_bool<T: AsRef<OsStr>>(name: T) -> Option<bool> {
-    match &*get(name)? {
-        "off" | "OFF" | "FALSE" | "false" | "0" => Some(false),
-        "on" | "ON" | "TRUE" | "true" | "1" => Some(true),
+    ENV.get_bool(name)
+}
+
+/// Gets a log level environment variable.
+///
+/// Returns None if the variable does not exist or the level is unrecognized.
+///
+/// Recognized values are `trace`, `debug` (or `dbg`), `info`, `warn` (or `warning`) and `error`
+/// (or `err`). Values are matched case-insensitively, for example `LOG=Warning` is read as
+/// [LogLevel::Warn].
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_level<T: AsRef<OsStr>>(name: T) -> Option<LogLevel> {
+    LogLevel::parse(&get(name)?)
+}
+
+/// Gets an environment variable and matches it against a fixed set of values.
+///
+/// Each `variant => "text"` pair maps a text to a value, usually an enum variant. Values are
+/// matched case-insensitively (ASCII only) and the first matching pair wins. The macro evaluates
+/// to None if the variable does not exist, is not valid UTF-8 or doesn't match any text.
+///
+/// This macro goes through [get] and therefore shares the same cache.
+///
+/// # Examples
+///
+/// ```
+/// #[derive(Debug, PartialEq)]
+/// enum Mode {
+///     Fast,
+///     Balanced,
+///     Safe
+/// }
+///
+/// let mode = |name| {
+///     bp3d_env::get_enum!(
+///         name,
+///         Mode::Fast => "fast",
+///         Mode::Balanced => "balanced",
+///         Mode::Safe => "safe"
+///     )
+/// };
+/// bp3d_env::set_override("GET_ENUM_MODE", "Balanced");
+/// assert_eq!(mode("GET_ENUM_MODE"), Some(Mode::Balanced));
+/// bp3d_env::set_override("GET_ENUM_MODE", "turbo");
+/// assert_eq!(mode("GET_ENUM_MODE"), None);
+/// assert_eq!(mode("GET_ENUM_UNDEFINED"), None);
+/// ```
+#[macro_export]
+macro_rules! get_enum {
+    ($name: expr, $($variant: path => $text: literal),+ $(,)?) => {
+        $crate::get($name).and_then(|value| {
+            $(
+                if value.eq_ignore_ascii_case($text) {
+                    return Some($variant);
+                }
+            )+
+            None
+        })
+    };
+}
+
+/// Gets a boolean environment variable or a default value.
+///
+/// This is the same as [get_bool_with_default], which should be used instead.
+#[deprecated(since = "2.0.0", note = "use get_bool_with_default instead")]
+pub fn get_bool_or<T: AsRef<OsStr>>(name: T, default: bool) -> bool {
+    get_bool_with_default(name, default)
+}
+
+/// Gets a boolean environment variable or a default value.
+///
+/// Returns default if the variable does not exist, is empty (for example a `FLAG=` line in an
+/// override file) or if its format is unrecognized, and the parsed value otherwise (see
+/// [get_bool] for the recognized values). This intentionally collapses the unset, empty and
+/// invalid cases into the default, replacing the common `get_bool(name).unwrap_or(false)` idiom;
+/// use [get_bool] or [get_bool_strict] to tell them apart.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("FLAG", "");
+/// assert!(!bp3d_env::get_bool_with_default("FLAG", false));
+/// bp3d_env::set_override("FLAG", "on");
+/// assert!(bp3d_env::get_bool_with_default("FLAG", false));
+/// ```
+pub fn get_bool_with_default<T: AsRef<OsStr>>(name: T, default: bool) -> bool {
+    ENV.get_bool_with_default(name, default)
+}
+
+/// Gets a boolean environment variable, failing on unrecognized values.
+///
+/// Returns Ok(None) if the variable does not exist or is empty and Ok(Some) if its value is
+/// recognized. The recognized values are the same as [get_bool].
+///
+/// # Errors
+///
+/// Returns a [ParseBoolError] carrying the offending value if the variable is present but its
+/// value is not recognized, including when it's not valid UTF-8.
+pub fn get_bool_strict<T: AsRef<OsStr>>(name: T) -> Result<Option<bool>, ParseBoolError> {
+    let value = match get_os(name) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let value = value.to_string_lossy();
+    match parser::parse_bool(&value) {
+        Some(v) => Ok(Some(v)),
+        None => Err(ParseBoolError::new(value.into())),
+    }
+}
+
+/// Gets an environment variable and parses it using [FromStr].
+///
+/// Returns None if the variable does not exist, is not valid UTF-8 or could not be parsed.
+///
+/// **Note: a value which fails to parse is indistinguishable from a missing value.**
+///
+/// This function goes through [get] and therefore shares the same cache: repeated calls do not
+/// re-read the override files.
+///
+/// # Examples
+///
+/// ```
+/// let port = bp3d_env::get_parse::<_, u16>("PORT");
+/// ```
+pub fn get_parse<T: AsRef<OsStr>, F: FromStr>(name: T) -> Option<F> {
+    get(name)?.parse().ok()
+}
+
+/// Gets an environment variable and parses it using [FromStr], or returns the default value of
+/// the type.
+///
+/// Returns F::default() if the variable does not exist, is not valid UTF-8 or could not be
+/// parsed.
+///
+/// **Note: invalid values silently fall back to the default, use [try_get_parse] to detect
+/// them.**
+///
+/// This function goes through [get] and therefore shares the same cache.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("GET_DEFAULT_WORKERS", "4");
+/// bp3d_env::set_override("GET_DEFAULT_INVALID", "four");
+/// bp3d_env::set_override("GET_DEFAULT_FLAG", "true");
+/// assert_eq!(bp3d_env::get_default::<_, u32>("GET_DEFAULT_WORKERS"), 4);
+/// assert_eq!(bp3d_env::get_default::<_, u32>("GET_DEFAULT_INVALID"), 0);
+/// assert_eq!(bp3d_env::get_default::<_, u32>("GET_DEFAULT_MISSING"), 0);
+/// // bool only accepts "true" and "false", see get_bool_with_default for more formats.
+/// assert!(bp3d_env::get_default::<_, bool>("GET_DEFAULT_FLAG"));
+/// assert!(!bp3d_env::get_default::<_, bool>("GET_DEFAULT_MISSING"));
+/// ```
+pub fn get_default<T: AsRef<OsStr>, F: FromStr + Default>(name: T) -> F {
+    get_parse(name).unwrap_or_default()
+}
+
+/// Gets an environment variable and parses it using [FromStr], returning the parse error.
+///
+/// Returns Ok(None) if the variable does not exist, is empty or is not valid UTF-8 and Ok(Some)
+/// if its value could be parsed.
+///
+/// This function goes through [get] and therefore shares the same cache.
+///
+/// # Errors
+///
+/// Returns the error of [FromStr::from_str] if the variable is present but could not be parsed.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("TRY_GET_PARSE_PORT", "8080");
+/// bp3d_env::set_override("TRY_GET_PARSE_INVALID", "http");
+/// assert_eq!(bp3d_env::try_get_parse::<_, u16>("TRY_GET_PARSE_PORT"), Ok(Some(8080)));
+/// assert_eq!(bp3d_env::try_get_parse::<_, u16>("TRY_GET_PARSE_MISSING"), Ok(None));
+/// assert!(bp3d_env::try_get_parse::<_, u16>("TRY_GET_PARSE_INVALID").is_err());
+/// ```
+pub fn try_get_parse<T: AsRef<OsStr>, F: FromStr>(name: T) -> Result<Option<F>, F::Err> {
+    get(name).map(|v| v.parse()).transpose()
+}
+
+/// Gets a signed 32 bits integer environment variable.
+///
+/// Returns None if the variable does not exist, is not a decimal number with an optional sign or
+/// is outside of the range of [i32]. Values are never truncated.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_i32<T: AsRef<OsStr>>(name: T) -> Option<i32> {
+    get_parse(name)
+}
+
+/// Gets an unsigned 32 bits integer environment variable.
+///
+/// Returns None if the variable does not exist, is not a decimal number or is outside of the
+/// range of [u32]. Values are never truncated.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_u32<T: AsRef<OsStr>>(name: T) -> Option<u32> {
+    get_parse(name)
+}
+
+/// Gets a signed 64 bits integer environment variable.
+///
+/// Returns None if the variable does not exist, is not a number or overflows.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_i64<T: AsRef<OsStr>>(name: T) -> Option<i64> {
+    get_parse(name)
+}
+
+/// Gets a signed 64 bits integer environment variable written in any common radix.
+///
+/// The value may start with a sign and a radix prefix: `0x` or `0X` for hexadecimal, `0b` or `0B`
+/// for binary and `0o` or `0O` for octal; values without a prefix are decimal. Underscores are
+/// accepted as digit separators, for example `0xFF_FF`.
+///
+/// Returns None if the variable does not exist, is not a valid number in its radix or overflows.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_int_radix<T: AsRef<OsStr>>(name: T) -> Option<i64> {
+    parser::parse_int(&get(name)?)
+}
+
+/// Gets an unsigned 64 bits integer environment variable.
+///
+/// Returns None if the variable does not exist, is not a number or overflows.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_u64<T: AsRef<OsStr>>(name: T) -> Option<u64> {
+    get_parse(name)
+}
+
+/// Gets a pointer sized unsigned integer environment variable.
+///
+/// Returns None if the variable does not exist, is not a number or overflows.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_usize<T: AsRef<OsStr>>(name: T) -> Option<usize> {
+    get_parse(name)
+}
+
+/// Gets an unsigned 16 bits integer environment variable, such as a port number.
+///
+/// Returns None if the variable does not exist, is not a number or is outside of `0..=65535`.
+/// Values are never truncated.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_u16<T: AsRef<OsStr>>(name: T) -> Option<u16> {
+    get_parse(name)
+}
+
+/// Gets a single character environment variable.
+///
+/// Returns None if the variable does not exist or if its value is not exactly one Unicode scalar
+/// value.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_char<T: AsRef<OsStr>>(name: T) -> Option<char> {
+    get_parse(name)
+}
+
+/// Gets a 64 bits floating point environment variable.
+///
+/// Returns None if the variable does not exist or is not a valid floating point number.
+///
+/// Special values such as `inf` and `nan` are accepted as per the standard library parser.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_f64<T: AsRef<OsStr>>(name: T) -> Option<f64> {
+    get_parse(name)
+}
+
+/// Gets a 32 bits floating point environment variable.
+///
+/// Returns None if the variable does not exist or is not a valid floating point number.
+///
+/// Special values such as `inf` and `nan` are accepted as per the standard library parser.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_f32<T: AsRef<OsStr>>(name: T) -> Option<f32> {
+    get_parse(name)
+}
+
+/// Gets a path environment variable.
+///
+/// Returns None if the variable does not exist.
+///
+/// Unlike [get], the value is not required to be valid UTF-8 as it's directly converted from the
+/// underlying [OsString]. No existence check is performed on the returned path and no tilde or
+/// environment expansion is applied.
+///
+/// This function goes through [get_os] and therefore shares the same cache.
+pub fn get_path<T: AsRef<OsStr>>(name: T) -> Option<PathBuf> {
+    get_os(name).map(PathBuf::from)
+}
+
+/// Gets a socket address environment variable, such as `127.0.0.1:8080` or `[::1]:8080`.
+///
+/// Returns None if the variable does not exist or is not an IP address followed by a port. Host
+/// names are not resolved and are rejected.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_socket_addr<T: AsRef<OsStr>>(name: T) -> Option<SocketAddr> {
+    get_parse(name)
+}
+
+/// Gets an IP address environment variable, such as `127.0.0.1` or `::1`.
+///
+/// Returns None if the variable does not exist or is not a bare IPv4 or IPv6 address.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_ip_addr<T: AsRef<OsStr>>(name: T) -> Option<IpAddr> {
+    get_parse(name)
+}
+
+/// Gets a duration environment variable.
+///
+/// The accepted format is an unsigned integer immediately followed by an optional unit suffix:
+///
+/// - `ms` for milliseconds,
+/// - `s` for seconds,
+/// - `m` for minutes,
+/// - `h` for hours.
+///
+/// A bare integer is interpreted as a number of seconds. For example `500ms`, `30s`, `5m` and `30`
+/// are all valid durations.
+///
+/// Returns None if the variable does not exist, has an unknown suffix, a non-numeric prefix or
+/// overflows.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_duration<T: AsRef<OsStr>>(name: T) -> Option<Duration> {
+    let value = get(name)?;
+    let pos = value
+        .find(|v: char| !v.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(pos);
+    let number: u64 = number.parse().ok()?;
+    match unit {
+        "ms" => Some(Duration::from_millis(number)),
+        "" | "s" => Some(Duration::from_secs(number)),
+        "m" => number.checked_mul(60).map(Duration::from_secs),
+        "h" => number.checked_mul(3600).map(Duration::from_secs),
         _ => None,
     }
 }
+
+/// Gets a duration environment variable expressed as a fractional number of seconds.
+///
+/// The value is parsed as a floating point number of seconds, for example `2.5` is read as two
+/// and a half seconds.
+///
+/// Returns None if the variable does not exist or is not a number, and if the number is
+/// negative, NaN, infinite or too large to be represented by a [Duration].
+///
+/// This function goes through [get] and therefore shares the same cache.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// bp3d_env::set_override("DURATION_F64_HALF", "2.5");
+/// bp3d_env::set_override("DURATION_F64_ZERO", "0");
+/// bp3d_env::set_override("DURATION_F64_NEGATIVE", "-1");
+/// bp3d_env::set_override("DURATION_F64_NAN", "nan");
+/// let half = bp3d_env::get_duration_secs_f64("DURATION_F64_HALF");
+/// assert_eq!(half, Some(Duration::from_millis(2500)));
+/// let zero = bp3d_env::get_duration_secs_f64("DURATION_F64_ZERO");
+/// assert_eq!(zero, Some(Duration::ZERO));
+/// assert_eq!(bp3d_env::get_duration_secs_f64("DURATION_F64_NEGATIVE"), None);
+/// assert_eq!(bp3d_env::get_duration_secs_f64("DURATION_F64_NAN"), None);
+/// ```
+pub fn get_duration_secs_f64<T: AsRef<OsStr>>(name: T) -> Option<Duration> {
+    Duration::try_from_secs_f64(get_parse(name)?).ok()
+}
+
+/// Gets a byte size environment variable.
+///
+/// The accepted format is an unsigned integer immediately followed by an optional unit suffix:
+///
+/// - `KB`, `MB`, `GB` and `TB` for powers of 1000,
+/// - `KiB`, `MiB`, `GiB` and `TiB` for powers of 1024.
+///
+/// A bare integer is interpreted as a number of bytes. For example `256MB`, `1GiB` and `4096`
+/// are all valid sizes.
+///
+/// Returns None if the variable does not exist, has an unknown suffix, a non-numeric prefix or
+/// overflows.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_bytes<T: AsRef<OsStr>>(name: T) -> Option<u64> {
+    let value = get(name)?;
+    let pos = value
+        .find(|v: char| !v.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(pos);
+    let number: u64 = number.parse().ok()?;
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "KB" => 1000,
+        "MB" => 1000 * 1000,
+        "GB" => 1000 * 1000 * 1000,
+        "TB" => 1000 * 1000 * 1000 * 1000,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        _ => return None,
+    };
+    number.checked_mul(multiplier)
+}
+
+/// Gets a comma separated list environment variable.
+///
+/// See [get_list_with] for details.
+pub fn get_list<T: AsRef<OsStr>>(name: T) -> Option<Vec<String>> {
+    get_list_with(name, ',')
+}
+
+/// Gets a list environment variable using a custom delimiter.
+///
+/// Each element of the list is trimmed and empty elements are dropped.
+///
+/// Returns None if the variable does not exist or is not valid UTF-8. As empty values are treated
+/// as unset variables, a present but empty variable also returns None. A variable containing only
+/// delimiters and whitespace returns an empty list.
+///
+/// This function goes through [get] and therefore shares the same cache.
+pub fn get_list_with<T: AsRef<OsStr>>(name: T, delimiter: char) -> Option<Vec<String>> {
+    let value = get(name)?;
+    Some(
+        value
+            .split(delimiter)
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(String::from)
+            .collect()
+    )
+}
+
+/// Gets a list environment variable and parses each element using [FromStr].
+///
+/// The value is split on the delimiter, each element is trimmed and empty elements are dropped,
+/// like with [get_list_with]. The list is all-or-nothing: None is returned if any element could
+/// not be parsed, as well as if the variable does not exist or is not valid UTF-8. As empty
+/// values are treated as unset variables, a present but empty variable also returns None.
+///
+/// This function goes through [get] and therefore shares the same cache.
+///
+/// # Examples
+///
+/// ```
+/// bp3d_env::set_override("VEC_PARSE_PORTS", "8080, 8081,8082");
+/// let ports = bp3d_env::get_vec_parse::<_, u16>("VEC_PARSE_PORTS", ',');
+/// assert_eq!(ports, Some(vec![8080, 8081, 8082]));
+///
+/// bp3d_env::set_override("VEC_PARSE_BAD", "8080,http,8082");
+/// assert_eq!(bp3d_env::get_vec_parse::<_, u16>("VEC_PARSE_BAD", ','), None);
+///
+/// bp3d_env::set_override("VEC_PARSE_EMPTY", "");
+/// assert_eq!(bp3d_env::get_vec_parse::<_, u16>("VEC_PARSE_EMPTY", ','), None);
+/// ```
+pub fn get_vec_parse<T: AsRef<OsStr>, F: FromStr>(name: T, delimiter: char) -> Option<Vec<F>> {
+    let value = get(name)?;
+    value
+        .split(delimiter)
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| v.parse().ok())
+        .collect()
+}
+
+/// Returns statistics about the cache.
+///
+/// The returned [CacheStats] counts the lookups answered by the cache (hits), the lookups which
+/// had to resolve the variable from its sources (misses) and the number of times an override
+/// source was read, together with the current number of cache entries and of entries for
+/// variables which were not found. A high number of misses or source reads during startup
+/// suggests calling [preload].
+///
+/// The counters are cumulative and are only reset by [reset]. They include the lookups made by
+/// functions which resolve several variables such as [snapshot] and by variable interpolation.
+pub fn cache_stats() -> CacheStats {
+    ENV.cache_stats()
+}
+
+/// Returns all variables currently known by this crate.
+///
+/// The returned map merges the process environment with the content of every override file,
+/// using the same precedence rules as [get_os]. Variables with an empty value are omitted.
+///
+/// **Note: this is a heavy function which eagerly reads every override file and resolves every
+/// variable found.**
+///
+/// The values resolved by this function are not inserted in the cache.
+pub fn snapshot() -> HashMap<OsString, OsString> {
+    ENV.snapshot()
+}
+
+/// Returns all variables whose name starts with prefix, keyed by the rest of their name.
+///
+/// For example, with `PLUGIN_FOO_PATH=/tmp` and `PLUGIN_FOO_LEVEL=2`, `get_prefixed("PLUGIN_FOO_")`
+/// returns a map with the `PATH` and `LEVEL` keys. The prefix is matched case-sensitively and is
+/// not combined with the prefix set by [set_prefix]. Variables whose name or value is not valid
+/// UTF-8 are skipped.
+///
+/// Each value is resolved with the same precedence rules as [get_os]: when a variable is defined
+/// in several sources, the value returned by [get_os] is used. Variables with an empty value are
+/// omitted.
+///
+/// **Note: this is a heavy function with the same cost as [snapshot].**
+pub fn get_prefixed<T: AsRef<OsStr>>(prefix: T) -> HashMap<String, String> {
+    ENV.get_prefixed(prefix)
+}
+
+/// Eagerly loads every variable defined in the override files into the cache.
+///
+/// Each variable is resolved with the same precedence rules as [get_os], so a value from the
+/// process environment is not clobbered by an override file and the first override file defining
+/// a variable wins. Values already in the cache, including programmatic overrides, are kept. After
+/// this call, getters for variables defined in the override files are pure cache hits until the
+/// cache is invalidated; variables defined in no override file are still resolved lazily.
+///
+/// **Note: this is a heavy function which reads every override file, it's best called when
+/// initializing the application.**
+pub fn preload() {
+    ENV.preload()
+}
+
+/// Returns the names of all variables currently known by this crate, sorted and deduplicated.
+///
+/// The list is the union of the process environment, every override source and programmatic
+/// overrides. Consistent with [get_os], a variable whose value resolves to an empty string is
+/// considered unset and is omitted.
+///
+/// **Note: this is a heavy function which eagerly reads every override file and resolves every
+/// variable found, it has the same cost as [snapshot].**
+pub fn keys() -> Vec<OsString> {
+    ENV.keys()
+}
+
+/// Deserializes a structure from all variables starting with the given prefix.
+///
+/// Every variable whose name starts with `prefix` is collected; the prefix is then stripped and the
+/// remaining name is lowercased to obtain the field name. For example, with the prefix `APP_`, the
+/// variable `APP_LOG_LEVEL` maps to the field `log_level`. The prefix should therefore include any
+/// trailing separator.
+///
+/// String, boolean (using the same format as [get_bool]), integer and floating point fields are
+/// supported, as well as optional fields and unit enum variants.
+///
+/// **Note: this is a heavy function which calls [snapshot].**
+///
+/// **Note: this function is only available with the `serde` feature.**
+///
+/// # Errors
+///
+/// Returns an [EnvError] if a variable could not be parsed into the type of its field, in which
+/// case the error names the offending field, or if a required field is missing.
+#[cfg(feature = "serde")]
+pub fn from_env<T: serde::de::DeserializeOwned>(prefix: &str) -> Result<T, EnvError> {
+    ENV.from_env(prefix)
+}
+
+/// Gets an environment variable holding a JSON document and deserializes it.
+///
+/// Returns None if the variable does not exist, is not valid UTF-8 or is not a valid JSON
+/// representation of T. Use [try_get_json] to see deserialization errors.
+///
+/// **Note: this function is only available with the `json` feature.**
+///
+/// This function goes through [get] and therefore shares the same cache.
+#[cfg(feature = "json")]
+pub fn get_json<K: AsRef<OsStr>, T: serde::de::DeserializeOwned>(name: K) -> Option<T> {
+    try_get_json(name).ok().flatten()
+}
+
+/// Gets an environment variable holding a JSON document and deserializes it.
+///
+/// Returns Ok(None) if the variable does not exist or is not valid UTF-8.
+///
+/// **Note: this function is only available with the `json` feature.**
+///
+/// # Errors
+///
+/// Returns an error if the value is not a valid JSON representation of T.
+#[cfg(feature = "json")]
+pub fn try_get_json<K: AsRef<OsStr>, T: serde::de::DeserializeOwned>(
+    name: K
+) -> serde_json::Result<Option<T>> {
+    match get(name) {
+        Some(v) => serde_json::from_str(&v).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn get_path_preserves_non_utf8_override() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        let value = OsStr::from_bytes(b"/tmp/\xFF\xFEdata");
+        set_thread_override("GET_PATH_NON_UTF8_OVERRIDE", value);
+        let path = get_path("GET_PATH_NON_UTF8_OVERRIDE").unwrap();
+        assert_eq!(path.into_os_string().into_vec(), b"/tmp/\xFF\xFEdata");
+        // The lossy getters can't represent the value.
+        assert_eq!(get("GET_PATH_NON_UTF8_OVERRIDE"), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn get_path_preserves_non_utf8_file_value() {
+        use std::os::unix::ffi::OsStrExt;
+        add_override_reader(&b"GET_PATH_NON_UTF8_FILE=/data/\xC3\x28\n"[..]).unwrap();
+        let path = get_path("GET_PATH_NON_UTF8_FILE").unwrap();
+        assert_eq!(path.as_os_str().as_bytes(), b"/data/\xC3\x28");
+    }
+
+    #[test]
+    fn get_path_does_not_expand() {
+        set_thread_override("GET_PATH_TILDE", "~/$HOME/missing");
+        assert_eq!(get_path("GET_PATH_TILDE"), Some(PathBuf::from("~/$HOME/missing")));
+    }
+
+    #[test]
+    fn get_f64_round_trips_special_values() {
+        set_thread_override("GET_F64_INF", "inf");
+        set_thread_override("GET_F64_NEG_INF", "-inf");
+        set_thread_override("GET_F64_NAN", "NaN");
+        set_thread_override("GET_F64_GARBAGE", "1.0x");
+        set_thread_override("GET_F64_VALUE", "1.5");
+        assert_eq!(get_f64("GET_F64_INF"), Some(f64::INFINITY));
+        assert_eq!(get_f64("GET_F64_NEG_INF"), Some(f64::NEG_INFINITY));
+        assert!(get_f64("GET_F64_NAN").unwrap().is_nan());
+        assert!(get_f32("GET_F64_NAN").unwrap().is_nan());
+        assert_eq!(get_f64("GET_F64_GARBAGE"), None);
+        assert_eq!(get_f32("GET_F64_GARBAGE"), None);
+        assert_eq!(get_f64("GET_F64_VALUE"), Some(1.5));
+        assert_eq!(get_f32("GET_F64_VALUE"), Some(1.5));
+    }
+
+    #[test]
+    fn get_duration_units() {
+        set_thread_override("GET_DURATION_MS", "500ms");
+        set_thread_override("GET_DURATION_S", "30s");
+        set_thread_override("GET_DURATION_M", "5m");
+        set_thread_override("GET_DURATION_H", "2h");
+        set_thread_override("GET_DURATION_PLAIN", "30");
+        assert_eq!(get_duration("GET_DURATION_MS"), Some(Duration::from_millis(500)));
+        assert_eq!(get_duration("GET_DURATION_S"), Some(Duration::from_secs(30)));
+        assert_eq!(get_duration("GET_DURATION_M"), Some(Duration::from_secs(300)));
+        assert_eq!(get_duration("GET_DURATION_H"), Some(Duration::from_secs(7200)));
+        assert_eq!(get_duration("GET_DURATION_PLAIN"), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn get_duration_rejects_invalid_values() {
+        set_thread_override("GET_DURATION_UNIT", "5d");
+        set_thread_override("GET_DURATION_NUMBER", "ms");
+        set_thread_override("GET_DURATION_OVERFLOW", "18446744073709551615h");
+        assert_eq!(get_duration("GET_DURATION_UNIT"), None);
+        assert_eq!(get_duration("GET_DURATION_NUMBER"), None);
+        assert_eq!(get_duration("GET_DURATION_OVERFLOW"), None);
+        assert_eq!(get_duration("GET_DURATION_MISSING"), None);
+    }
+
+    #[test]
+    fn get_u16_range() {
+        set_thread_override("GET_U16_ZERO", "0");
+        set_thread_override("GET_U16_MAX", "65535");
+        set_thread_override("GET_U16_OVERFLOW", "65536");
+        set_thread_override("GET_U16_NEGATIVE", "-1");
+        assert_eq!(get_u16("GET_U16_ZERO"), Some(0));
+        assert_eq!(get_u16("GET_U16_MAX"), Some(65535));
+        assert_eq!(get_u16("GET_U16_OVERFLOW"), None);
+        assert_eq!(get_u16("GET_U16_NEGATIVE"), None);
+    }
+
+    #[test]
+    fn get_char_values() {
+        set_thread_override("GET_CHAR_ASCII", "x");
+        set_thread_override("GET_CHAR_MULTI_BYTE", "é");
+        set_thread_override("GET_CHAR_TWO", "ab");
+        set_thread_override("GET_CHAR_EMPTY", "");
+        assert_eq!(get_char("GET_CHAR_ASCII"), Some('x'));
+        assert_eq!(get_char("GET_CHAR_MULTI_BYTE"), Some('é'));
+        assert_eq!(get_char("GET_CHAR_TWO"), None);
+        assert_eq!(get_char("GET_CHAR_EMPTY"), None);
+    }
+
+    #[test]
+    fn get_i32_and_get_u32_boundaries() {
+        set_thread_override("GET_I32_MAX", "2147483647");
+        set_thread_override("GET_I32_MIN", "-2147483648");
+        set_thread_override("GET_I32_ABOVE", "2147483648");
+        set_thread_override("GET_I32_BELOW", "-2147483649");
+        set_thread_override("GET_U32_MAX", "4294967295");
+        set_thread_override("GET_U32_ABOVE", "4294967296");
+        set_thread_override("GET_U32_BELOW", "-1");
+        assert_eq!(get_i32("GET_I32_MAX"), Some(i32::MAX));
+        assert_eq!(get_i32("GET_I32_MIN"), Some(i32::MIN));
+        assert_eq!(get_i32("GET_I32_ABOVE"), None);
+        assert_eq!(get_i32("GET_I32_BELOW"), None);
+        assert_eq!(get_u32("GET_U32_MAX"), Some(u32::MAX));
+        assert_eq!(get_u32("GET_U32_ABOVE"), None);
+        assert_eq!(get_u32("GET_U32_BELOW"), None);
+    }
+
+    #[test]
+    fn get_bool_strict_outcomes() {
+        set_thread_override("GET_BOOL_STRICT_ON", "on");
+        set_thread_override("GET_BOOL_STRICT_INVALID", "maybe");
+        assert_eq!(get_bool_strict("GET_BOOL_STRICT_ON").unwrap(), Some(true));
+        assert_eq!(get_bool_strict("GET_BOOL_STRICT_MISSING").unwrap(), None);
+        let err = get_bool_strict("GET_BOOL_STRICT_INVALID").unwrap_err();
+        assert_eq!(err.value(), "maybe");
+    }
+
+    #[test]
+    fn get_socket_addr_values() {
+        set_thread_override("GET_SOCKET_ADDR_V4", "127.0.0.1:8080");
+        set_thread_override("GET_SOCKET_ADDR_V6", "[::1]:8080");
+        set_thread_override("GET_SOCKET_ADDR_HOST", "localhost:8080");
+        set_thread_override("GET_SOCKET_ADDR_NO_PORT", "127.0.0.1");
+        let v4 = get_socket_addr("GET_SOCKET_ADDR_V4").unwrap();
+        assert_eq!(v4, SocketAddr::from(([127, 0, 0, 1], 8080)));
+        let v6 = get_socket_addr("GET_SOCKET_ADDR_V6").unwrap();
+        assert!(v6.is_ipv6());
+        assert_eq!(v6.port(), 8080);
+        assert!(v6.ip().is_loopback());
+        assert_eq!(get_socket_addr("GET_SOCKET_ADDR_HOST"), None);
+        assert_eq!(get_socket_addr("GET_SOCKET_ADDR_NO_PORT"), None);
+    }
+
+    #[test]
+    fn get_nonempty_values() {
+        set_thread_override("GET_NONEMPTY_EMPTY", "");
+        set_thread_override("GET_NONEMPTY_BLANK", " \t ");
+        set_thread_override("GET_NONEMPTY_PADDED", "  value \t");
+        set_thread_override("GET_NONEMPTY_VALUE", "value");
+        assert_eq!(get_nonempty("GET_NONEMPTY_EMPTY"), None);
+        assert_eq!(get_nonempty("GET_NONEMPTY_BLANK"), None);
+        assert_eq!(get_nonempty("GET_NONEMPTY_PADDED").as_deref(), Some("value"));
+        assert_eq!(get_nonempty("GET_NONEMPTY_VALUE").as_deref(), Some("value"));
+    }
+
+    #[test]
+    fn get_bytes_suffixes() {
+        let cases = [
+            ("512", Some(512)),
+            ("2KB", Some(2_000)),
+            ("2MB", Some(2_000_000)),
+            ("2GB", Some(2_000_000_000)),
+            ("2TB", Some(2_000_000_000_000)),
+            ("2KiB", Some(2 << 10)),
+            ("2MiB", Some(2 << 20)),
+            ("2GiB", Some(2 << 30)),
+            ("2TiB", Some(2 << 40)),
+            ("2kb", None),
+            ("2 KB", None),
+            ("KB", None),
+        ];
+        for (value, expected) in cases {
+            set_thread_override("GET_BYTES_CASE", value);
+            assert_eq!(get_bytes("GET_BYTES_CASE"), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn get_bytes_overflow() {
+        set_thread_override("GET_BYTES_MAX", "16777215TiB");
+        set_thread_override("GET_BYTES_OVERFLOW", "16777216TiB");
+        assert_eq!(get_bytes("GET_BYTES_MAX"), Some(16777215 << 40));
+        assert_eq!(get_bytes("GET_BYTES_OVERFLOW"), None);
+    }
+
+    #[test]
+    fn get_any_returns_the_first_defined_name() {
+        set_thread_override("GET_ANY_OLD", "old");
+        assert_eq!(get_any(&["GET_ANY_NEW", "GET_ANY_OLD"]).as_deref(), Some("old"));
+        set_thread_override("GET_ANY_NEW", "new");
+        assert_eq!(get_any(&["GET_ANY_NEW", "GET_ANY_OLD"]).as_deref(), Some("new"));
+        assert_eq!(get_any(&["GET_ANY_MISSING_A", "GET_ANY_MISSING_B"]), None);
+        assert_eq!(get_any_os(&["GET_ANY_MISSING_A", "GET_ANY_OLD"]), Some("old".into()));
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn get_json_values() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+        set_thread_override("GET_JSON_STRUCT", r#"{"host": "localhost", "port": 8080}"#);
+        set_thread_override("GET_JSON_VEC", r#"["a", "b"]"#);
+        set_thread_override("GET_JSON_INVALID", "{");
+        let server = Server {
+            host: "localhost".into(),
+            port: 8080,
+        };
+        assert_eq!(get_json("GET_JSON_STRUCT"), Some(server));
+        let list: Option<Vec<String>> = get_json("GET_JSON_VEC");
+        assert_eq!(list, Some(vec!["a".to_string(), "b".to_string()]));
+        assert!(try_get_json::<_, Vec<String>>("GET_JSON_INVALID").is_err());
+        assert_eq!(get_json::<_, Vec<String>>("GET_JSON_MISSING"), None);
+    }
+
+    #[test]
+    fn get_level_values() {
+        set_thread_override("GET_LEVEL_WARN", "warning");
+        set_thread_override("GET_LEVEL_INVALID", "loud");
+        assert_eq!(get_level("GET_LEVEL_WARN"), Some(LogLevel::Warn));
+        assert_eq!(get_level("GET_LEVEL_INVALID"), None);
+        assert_eq!(get_level("GET_LEVEL_MISSING"), None);
+    }
+
+    #[test]
+    fn get_or_else_is_lazy() {
+        set_thread_override("GET_OR_ELSE_LAZY", "value");
+        let value = get_or_else("GET_OR_ELSE_LAZY", || panic!("default computed"));
+        assert_eq!(value, "value");
+        let mut calls = 0;
+        let value = get_or_else("GET_OR_ELSE_LAZY_MISSING", || {
+            calls += 1;
+            "default".into()
+        });
+        assert_eq!(value, "default");
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn try_get_parse_outcomes() {
+        set_thread_override("TRY_GET_PARSE_U16", "8080");
+        set_thread_override("TRY_GET_PARSE_U16_INVALID", "70000");
+        assert_eq!(try_get_parse::<_, u16>("TRY_GET_PARSE_U16"), Ok(Some(8080)));
+        assert_eq!(try_get_parse::<_, u16>("TRY_GET_PARSE_U16_MISSING"), Ok(None));
+        let err = try_get_parse::<_, u16>("TRY_GET_PARSE_U16_INVALID").unwrap_err();
+        assert_eq!(err, "70000".parse::<u16>().unwrap_err());
+    }
+
+    #[test]
+    fn get_duration_secs_f64_values() {
+        set_thread_override("SECS_F64_HALF", "2.5");
+        set_thread_override("SECS_F64_ZERO", "0");
+        set_thread_override("SECS_F64_NEGATIVE", "-1");
+        set_thread_override("SECS_F64_NAN", "nan");
+        set_thread_override("SECS_F64_INF", "inf");
+        let half = get_duration_secs_f64("SECS_F64_HALF");
+        assert_eq!(half, Some(Duration::from_millis(2500)));
+        assert_eq!(get_duration_secs_f64("SECS_F64_ZERO"), Some(Duration::ZERO));
+        assert_eq!(get_duration_secs_f64("SECS_F64_NEGATIVE"), None);
+        assert_eq!(get_duration_secs_f64("SECS_F64_NAN"), None);
+        assert_eq!(get_duration_secs_f64("SECS_F64_INF"), None);
+    }
+
+    #[test]
+    fn get_vec_parse_values() {
+        set_thread_override("VEC_PARSE_VALID", "1, 2,3");
+        set_thread_override("VEC_PARSE_INVALID", "1,two,3");
+        set_thread_override("VEC_PARSE_EMPTY", "");
+        let valid: Option<Vec<u16>> = get_vec_parse("VEC_PARSE_VALID", ',');
+        assert_eq!(valid, Some(vec![1, 2, 3]));
+        assert_eq!(get_vec_parse::<_, u16>("VEC_PARSE_INVALID", ','), None);
+        // An empty value is treated as unset.
+        assert_eq!(get_vec_parse::<_, u16>("VEC_PARSE_EMPTY", ','), None);
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Lossless override file editing.
+
+use crate::file::os_str_from_bytes;
+use crate::key::keys_equal;
+use crate::parser;
+use std::ffi::OsStr;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The UTF-8 byte order mark.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// An override file loaded for editing.
+///
+/// Unlike the getters of this crate, which only see the resolved values, this keeps every line
+/// of the file as it was written: comments, blank lines, directives and the order of the entries
+/// are preserved when the file is saved. Only the lines changed with [set](Self::set) are
+/// rewritten.
+///
+/// Duplicate keys are handled like with [DuplicatePolicy::FirstWins](crate::DuplicatePolicy):
+/// [get](Self::get) and [set](Self::set) use the first definition of a key. Only UTF-8 files are
+/// supported.
+#[derive(Debug, Clone)]
+pub struct OverrideFile {
+    path: PathBuf,
+    delimiter: u8,
+    bom: bool,
+    /// The raw text of each line; lines joined by a continuation are stored together.
+    lines: Vec<Vec<u8>>,
+    trailing_newline: bool,
+}
+
+/// Returns the content of a raw line with line endings and continuations removed.
+fn logical(raw: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(raw.len());
+    for (i, v) in raw.split(|v| *v == b'\n').enumerate() {
+        if i > 0 {
+            parser::strip_continuation(&mut data);
+        }
+        data.extend_from_slice(v.strip_suffix(b"\r").unwrap_or(v));
+    }
+    data
+}
+
+/// Encodes a value so that it's read back unchanged, quoting it only when needed.
+fn encode_value(value: &str) -> Vec<u8> {
+    let special = |v: char| matches!(v, '#' | '"' | '\'' | '\\' | '\n' | '\t' | '\r');
+    if !value.contains(special) && value.trim() == value {
+        return value.as_bytes().to_vec();
+    }
+    let mut res = vec![b'"'];
+    for c in value.bytes() {
+        match c {
+            b'\\' => res.extend_from_slice(b"\\\\"),
+            b'"' => res.extend_from_slice(b"\\\""),
+            b'\n' => res.extend_from_slice(b"\\n"),
+            b'\t' => res.extend_from_slice(b"\\t"),
+            v => res.push(v),
+        }
+    }
+    res.push(b'"');
+    res
+}
+
+impl OverrideFile {
+    /// Loads an override file for editing.
+    ///
+    /// The file is parsed with the default `=` delimiter, see
+    /// [set_delimiter](Self::set_delimiter) to use another delimiter.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be read.
+    pub fn load<T: AsRef<Path>>(path: T) -> std::io::Result<OverrideFile> {
+        let path = path.as_ref();
+        let mut data = std::fs::read(path)?;
+        let bom = data.starts_with(BOM);
+        if bom {
+            data.drain(..BOM.len());
+        }
+        let trailing_newline = data.last() == Some(&b'\n');
+        if trailing_newline {
+            data.pop();
+        }
+        let mut lines = Vec::new();
+        let mut physical = data.split(|v| *v == b'\n');
+        while let Some(first) = physical.next() {
+            let mut raw = first.to_vec();
+            let mut data = first.strip_suffix(b"\r").unwrap_or(first).to_vec();
+            while parser::strip_continuation(&mut data) {
+                let next = match physical.next() {
+                    Some(v) => v,
+                    None => break,
+                };
+                raw.push(b'\n');
+                raw.extend_from_slice(next);
+                data.extend_from_slice(next.strip_suffix(b"\r").unwrap_or(next));
+            }
+            lines.push(raw);
+        }
+        // An empty file has no line.
+        if lines == [Vec::<u8>::new()] && !trailing_newline {
+            lines.clear();
+        }
+        Ok(OverrideFile {
+            path: path.into(),
+            delimiter: b'=',
+            bom,
+            lines,
+            trailing_newline,
+        })
+    }
+
+    /// Sets the delimiter between keys and values, which defaults to `=`.
+    pub fn set_delimiter(&mut self, delimiter: u8) {
+        self.delimiter = delimiter;
+    }
+
+    /// Returns the path this file was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the index of the line defining a key together with the position of its value.
+    fn find(&self, key: &OsStr) -> Option<(usize, Vec<u8>)> {
+        self.lines.iter().enumerate().find_map(|(i, raw)| {
+            let data = logical(raw);
+            let (k, _) = parser::split_line(&data, self.delimiter)?;
+            if !keys_equal(os_str_from_bytes(k)?, key) {
+                return None;
+            }
+            Some((i, data))
+        })
+    }
+
+    /// Returns the value of a key, as read by the getters of this crate.
+    ///
+    /// Returns None if the key is not defined or if its value is not valid UTF-8. Values are
+    /// returned before interpolation.
+    pub fn get<T: AsRef<OsStr>>(&self, key: T) -> Option<String> {
+        let (_, data) = self.find(key.as_ref())?;
+        let (_, value) = parser::split_line(&data, self.delimiter)?;
+        String::from_utf8(parser::parse_value(value)).ok()
+    }
+
+    /// Sets the value of a key.
+    ///
+    /// If the key is defined, the line defining it is rewritten: the key and the spacing around
+    /// the delimiter are kept but a comment following the value is removed. Otherwise, a new line
+    /// is appended to the end of the file. The value is quoted and escaped when needed so that
+    /// it's read back unchanged.
+    ///
+    /// **Note: the key must not contain the delimiter, as such a line could not be read back.**
+    pub fn set<T: AsRef<str>, V: AsRef<str>>(&mut self, key: T, value: V) {
+        let key = key.as_ref();
+        let value = encode_value(value.as_ref());
+        match self.find(OsStr::new(key)) {
+            Some((i, data)) => {
+                // Keep everything up to the value, including the whitespace which precedes it.
+                let pos = data.iter().position(|v| *v == self.delimiter).unwrap_or(0) + 1;
+                let start = data[pos..]
+                    .iter()
+                    .position(|v| !v.is_ascii_whitespace())
+                    .map(|v| pos + v)
+                    .unwrap_or(data.len());
+                let mut raw = data[..start].to_vec();
+                raw.extend_from_slice(&value);
+                if self.lines[i].ends_with(b"\r") {
+                    raw.push(b'\r');
+                }
+                self.lines[i] = raw;
+            }
+            None => {
+                let mut raw = key.as_bytes().to_vec();
+                raw.push(self.delimiter);
+                raw.extend_from_slice(&value);
+                // Follow the line endings of the file.
+                if self.lines.last().is_some_and(|v| v.ends_with(b"\r")) {
+                    raw.push(b'\r');
+                }
+                if self.lines.is_empty() {
+                    self.trailing_newline = true;
+                }
+                self.lines.push(raw);
+            }
+        }
+    }
+
+    /// Writes this file to a writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer failed.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> std::io::Result<()> {
+        if self.bom {
+            writer.write_all(BOM)?;
+        }
+        for (i, raw) in self.lines.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b"\n")?;
+            }
+            writer.write_all(raw)?;
+        }
+        if self.trailing_newline && !self.lines.is_empty() {
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Writes this file back to the path it was loaded from.
+    ///
+    /// **Note: the file is not reloaded by the getters until the cache is invalidated, see
+    /// [reload_path](crate::reload_path).**
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file could not be written.
+    pub fn save(&self) -> std::io::Result<()> {
+        let mut data = Vec::new();
+        self.write_to(&mut data)?;
+        std::fs::write(&self.path, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a file in the temporary directory, unique to this process.
+    fn write_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("bp3d_env_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn round_trip_without_changes() {
+        let contents = "# header\n\nA=1\n  # indented comment\nB = \"two words\"\n\n@unset C\n";
+        let path = write_file("round_trip.env", contents);
+        let file = OverrideFile::load(&path).unwrap();
+        file.save().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+    }
+
+    #[test]
+    fn set_preserves_comments_and_blank_lines() {
+        let path = write_file("edit.env", "# header\n\nA=1\n\n# section\nB = 2\n");
+        let mut file = OverrideFile::load(&path).unwrap();
+        assert_eq!(file.get("B").as_deref(), Some("2"));
+        file.set("B", "3");
+        file.set("C", "new value");
+        file.save().unwrap();
+        let expected = "# header\n\nA=1\n\n# section\nB = 3\nC=new value\n";
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        let file = OverrideFile::load(&path).unwrap();
+        assert_eq!(file.get("B").as_deref(), Some("3"));
+        assert_eq!(file.get("C").as_deref(), Some("new value"));
+    }
+
+    #[test]
+    fn set_quotes_special_values() {
+        let path = write_file("quote.env", "A=1\r\n");
+        let mut file = OverrideFile::load(&path).unwrap();
+        file.set("A", "a # b");
+        let mut data = Vec::new();
+        file.write_to(&mut data).unwrap();
+        std::fs::write(&path, &data).unwrap();
+        assert!(data.ends_with(b"\r\n"));
+        let file = OverrideFile::load(&path).unwrap();
+        assert_eq!(file.get("A").as_deref(), Some("a # b"));
+    }
+}
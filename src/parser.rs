@@ -0,0 +1,490 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Override file line parser.
+
+/// Returns true if the line is blank or is a comment line (first non-whitespace byte is `#`).
+pub fn is_ignored(line: &[u8]) -> bool {
+    matches!(line.iter().find(|v| !v.is_ascii_whitespace()), None | Some(b'#'))
+}
+
+/// Handles a backslash at the end of a physical line.
+///
+/// A line ending with an odd number of backslashes continues on the next physical line: the last
+/// backslash is removed and true is returned. With an even number of backslashes, the line ends
+/// with escaped backslashes (`\\` is read as a literal backslash by [parse_value]): the line is
+/// kept as is and false is returned. Comment lines are never continued.
+pub fn strip_continuation(line: &mut Vec<u8>) -> bool {
+    if is_ignored(line) {
+        return false;
+    }
+    let count = line.iter().rev().take_while(|v| **v == b'\\').count();
+    if count % 2 == 0 {
+        return false;
+    }
+    line.pop();
+    true
+}
+
+/// Splits a line of an override file into its key and raw value parts at the first delimiter.
+///
+/// Leading and trailing ASCII whitespace is removed from both the key and the value. A leading
+/// `export ` prefix is removed from the key for compatibility with shell scripts.
+///
+/// Returns None if the line should be ignored: either it's a comment line (first non-whitespace
+/// byte is `#`), it does not contain the delimiter or its key is empty.
+pub fn split_line(line: &[u8], delimiter: u8) -> Option<(&[u8], &[u8])> {
+    if is_ignored(line) {
+        return None;
+    }
+    let pos = line.iter().position(|v| *v == delimiter)?;
+    let key = strip_export(line[..pos].trim_ascii());
+    if key.is_empty() {
+        return None;
+    }
+    Some((key, line[pos + 1..].trim_ascii()))
+}
+
+/// Returns the argument of an `@name argument` directive, or None if the line is not this
+/// directive.
+fn parse_directive<'a>(line: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    let rest = line.trim_ascii().strip_prefix(b"@")?.strip_prefix(name)?;
+    if !rest.first()?.is_ascii_whitespace() {
+        return None;
+    }
+    Some(rest.trim_ascii())
+}
+
+/// Returns the path of an `@include path` directive, or None if the line is not a directive.
+pub fn parse_include(line: &[u8]) -> Option<&[u8]> {
+    parse_directive(line, b"include")
+}
+
+/// Returns the key of an `@unset KEY` directive, or None if the line is not a directive.
+pub fn parse_unset(line: &[u8]) -> Option<&[u8]> {
+    parse_directive(line, b"unset")
+}
+
+/// Strips a leading `export` keyword from a key, as found in shell scripts.
+///
+/// The keyword is only stripped when followed by whitespace and a valid key (a non-empty key
+/// without whitespace).
+fn strip_export(key: &[u8]) -> &[u8] {
+    match key.strip_prefix(b"export") {
+        Some(rest) if rest.first().map(|v| v.is_ascii_whitespace()).unwrap_or(false) => {
+            let rest = rest.trim_ascii_start();
+            if rest.iter().any(|v| v.is_ascii_whitespace()) {
+                key
+            } else {
+                rest
+            }
+        }
+        _ => key,
+    }
+}
+
+/// Finds the index of the quote closing the quoted string starting at the beginning of data.
+fn find_closing_quote(data: &[u8]) -> Option<usize> {
+    let quote = data[0];
+    let mut i = 1;
+    while i < data.len() {
+        match data[i] {
+            b'\\' => i += 1,
+            v if v == quote => return Some(i),
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns the byte represented by the escape sequence `\c`, if c is a recognized escape.
+fn unescape(c: u8) -> Option<u8> {
+    match c {
+        b'n' => Some(b'\n'),
+        b't' => Some(b'\t'),
+        b'\\' => Some(b'\\'),
+        _ => None,
+    }
+}
+
+/// Copies the content of a quoted string, unescaping any escaped quote character.
+///
+/// Double-quoted strings additionally support the escape sequences recognized by [unescape].
+fn parse_quoted(data: &[u8], quote: u8, value: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'\\' {
+            let escaped = match data.get(i + 1) {
+                Some(&v) if v == quote => Some(v),
+                Some(&v) if quote == b'"' => unescape(v),
+                _ => None,
+            };
+            if let Some(v) = escaped {
+                value.push(v);
+                i += 2;
+                continue;
+            }
+        }
+        value.push(data[i]);
+        i += 1;
+    }
+}
+
+/// Copies an unquoted value, stopping at the first unescaped `#`.
+///
+/// Backslashes are kept as is, except in `\#` which is read as a literal `#`, so that Windows
+/// paths such as `C:\new\temp` or `\\server\share` are read unchanged.
+fn parse_unquoted(data: &[u8], value: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'\\' if data.get(i + 1) == Some(&b'#') => {
+                value.push(b'#');
+                i += 1;
+            }
+            b'#' => {
+                while value.last().map(|v| v.is_ascii_whitespace()).unwrap_or(false) {
+                    value.pop();
+                }
+                break;
+            }
+            v => value.push(v),
+        }
+        i += 1;
+    }
+}
+
+/// Parses the raw value part of a line.
+///
+/// Anything following an unescaped `#` is considered a comment and is removed from the value
+/// together with the whitespace preceding it. A `#` inside a quoted value does not start a
+/// comment and a literal `#` can be written as `\#`.
+///
+/// A value wrapped in matching single or double quotes has its quotes removed, escaped quotes
+/// (`\"` or `\'`) are unescaped. Double-quoted values additionally support the escape sequences
+/// `\n` (newline), `\t` (tab) and `\\` (backslash), like in dotenv files; a backslash not followed
+/// by a recognized escape is kept as is. The content of a single-quoted value is otherwise kept
+/// verbatim. A value with an unterminated quote, or with content following the closing quote, is
+/// kept literally.
+///
+/// Backslashes in unquoted values are kept as is, except in `\#`, so that Windows paths such as
+/// `C:\new\temp` are read unchanged.
+pub fn parse_value(raw: &[u8]) -> Vec<u8> {
+    let mut value = Vec::with_capacity(raw.len());
+    if let Some(&quote @ (b'"' | b'\'')) = raw.first() {
+        if let Some(end) = find_closing_quote(raw) {
+            parse_unquoted(&raw[end + 1..], &mut value);
+            if value.trim_ascii().is_empty() {
+                value.clear();
+                parse_quoted(&raw[1..end], quote, &mut value);
+                return value;
+            }
+            // Something follows the closing quote: keep the quoted string as is.
+            let mut literal = raw[..=end].to_vec();
+            literal.append(&mut value);
+            return literal;
+        }
+    }
+    parse_unquoted(raw, &mut value);
+    value
+}
+
+/// Parses a boolean value.
+///
+/// Recognized values are `on`, `true`, `yes`, `y` and `1` for true and `off`, `false`, `no`, `n`
+/// and `0` for false. Values are matched case-insensitively.
+pub fn parse_bool(value: &str) -> Option<bool> {
+    const FALSE: [&str; 5] = ["off", "false", "no", "n", "0"];
+    const TRUE: [&str; 5] = ["on", "true", "yes", "y", "1"];
+    if FALSE.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+        Some(false)
+    } else if TRUE.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Parses an integer with an optional sign and radix prefix.
+///
+/// A `0x`/`0X` prefix denotes hexadecimal, `0b`/`0B` binary and `0o`/`0O` octal; values without a
+/// prefix are decimal. Underscores are accepted as digit separators and ignored.
+pub fn parse_int(value: &str) -> Option<i64> {
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(v) => ("-", v),
+        None => ("", value.strip_prefix('+').unwrap_or(value)),
+    };
+    let (radix, digits) = match value.get(..2) {
+        Some("0x" | "0X") => (16, &value[2..]),
+        Some("0b" | "0B") => (2, &value[2..]),
+        Some("0o" | "0O") => (8, &value[2..]),
+        _ => (10, value),
+    };
+    let mut number = String::with_capacity(digits.len() + 1);
+    number.push_str(sign);
+    for c in digits.chars().filter(|v| *v != '_') {
+        if !c.is_ascii_alphanumeric() {
+            return None;
+        }
+        number.push(c);
+    }
+    i64::from_str_radix(&number, radix).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continuation_single_backslash() {
+        let mut line = b"KEY=part1\\".to_vec();
+        assert!(strip_continuation(&mut line));
+        assert_eq!(line, b"KEY=part1");
+    }
+
+    #[test]
+    fn continuation_escaped_backslash() {
+        let mut line = b"KEY=part1\\\\".to_vec();
+        assert!(!strip_continuation(&mut line));
+        assert_eq!(line, b"KEY=part1\\\\");
+        assert_eq!(parse_value(b"part1\\\\"), b"part1\\\\");
+        assert_eq!(parse_value(b"\"part1\\\\\""), b"part1\\");
+    }
+
+    #[test]
+    fn continuation_escaped_backslash_then_continuation() {
+        let mut line = b"A=x\\\\\\".to_vec();
+        assert!(strip_continuation(&mut line));
+        assert_eq!(line, b"A=x\\\\");
+    }
+
+    #[test]
+    fn continuation_ignored_in_comments() {
+        let mut line = b"# comment\\".to_vec();
+        assert!(!strip_continuation(&mut line));
+        assert_eq!(line, b"# comment\\");
+    }
+
+    #[test]
+    fn comment_lines_are_ignored() {
+        assert_eq!(split_line(b"# this is a comment", b'='), None);
+        assert_eq!(split_line(b"   # indented comment", b'='), None);
+        assert_eq!(split_line(b"#KEY=value", b'='), None);
+        assert!(is_ignored(b""));
+        assert!(is_ignored(b" \t"));
+        assert!(!is_ignored(b"KEY=value # note"));
+    }
+
+    #[test]
+    fn trailing_comments_are_stripped() {
+        assert_eq!(parse_value(b"value # note"), b"value");
+        assert_eq!(parse_value(b"value\t#note"), b"value");
+        assert_eq!(parse_value(b"value#note"), b"value");
+        assert_eq!(parse_value(b"# only a comment"), b"");
+    }
+
+    #[test]
+    fn hash_inside_quotes_is_kept() {
+        assert_eq!(parse_value(b"\"a # b\""), b"a # b");
+        assert_eq!(parse_value(b"'#a'"), b"#a");
+        assert_eq!(parse_value(b"\"a # b\" # note"), b"a # b");
+    }
+
+    #[test]
+    fn escaped_hash_is_kept() {
+        assert_eq!(parse_value(b"\\#start"), b"#start");
+        assert_eq!(parse_value(b"a\\#b # note"), b"a#b");
+    }
+
+    #[test]
+    fn double_quoted_values() {
+        let (key, value) = split_line(b"FILTER=\"a=b c=d\"", b'=').unwrap();
+        assert_eq!(key, b"FILTER");
+        assert_eq!(value, b"\"a=b c=d\"");
+        assert_eq!(parse_value(b"\"a=b c=d\""), b"a=b c=d");
+        assert_eq!(parse_value(b"\"  padded  \""), b"  padded  ");
+        assert_eq!(parse_value(b"\"\""), b"");
+    }
+
+    #[test]
+    fn single_quoted_values() {
+        assert_eq!(parse_value(b"'a=b c=d'"), b"a=b c=d");
+        // Escape sequences other than the quote are kept verbatim in single quotes.
+        assert_eq!(parse_value(b"'a\\nb'"), b"a\\nb");
+    }
+
+    #[test]
+    fn escaped_quotes() {
+        assert_eq!(parse_value(b"\"say \\\"hi\\\"\""), b"say \"hi\"");
+        assert_eq!(parse_value(b"'it\\'s'"), b"it's");
+    }
+
+    #[test]
+    fn unterminated_or_mismatched_quotes_are_literal() {
+        assert_eq!(parse_value(b"\"abc"), b"\"abc");
+        assert_eq!(parse_value(b"'abc\""), b"'abc\"");
+        assert_eq!(parse_value(b"\"a\" b"), b"\"a\" b");
+        assert_eq!(parse_value(b"plain value"), b"plain value");
+    }
+
+    #[test]
+    fn export_prefix_is_stripped() {
+        assert_eq!(split_line(b"export A=1", b'='), Some((&b"A"[..], &b"1"[..])));
+        assert_eq!(split_line(b"export   A=1", b'='), Some((&b"A"[..], &b"1"[..])));
+        assert_eq!(split_line(b"export\tA=1", b'='), Some((&b"A"[..], &b"1"[..])));
+    }
+
+    #[test]
+    fn export_value_is_kept() {
+        assert_eq!(split_line(b"A=export", b'='), Some((&b"A"[..], &b"export"[..])));
+        assert_eq!(split_line(b"A=export B", b'='), Some((&b"A"[..], &b"export B"[..])));
+    }
+
+    #[test]
+    fn key_named_export() {
+        assert_eq!(split_line(b"export=1", b'='), Some((&b"export"[..], &b"1"[..])));
+        assert_eq!(split_line(b"export =1", b'='), Some((&b"export"[..], &b"1"[..])));
+        assert_eq!(split_line(b"EXPORTED=1", b'='), Some((&b"EXPORTED"[..], &b"1"[..])));
+        assert_eq!(split_line(b"my export=1", b'='), Some((&b"my export"[..], &b"1"[..])));
+        // Not followed by a valid key: the prefix is kept.
+        assert_eq!(split_line(b"export A B=1", b'='), Some((&b"export A B"[..], &b"1"[..])));
+    }
+
+    #[test]
+    fn escape_newline() {
+        assert_eq!(parse_value(b"line1\\nline2"), b"line1\\nline2");
+        assert_eq!(parse_value(b"\"line1\\nline2\""), b"line1\nline2");
+    }
+
+    #[test]
+    fn escape_tab() {
+        assert_eq!(parse_value(b"a\\tb"), b"a\\tb");
+        assert_eq!(parse_value(b"\"a\\tb\""), b"a\tb");
+    }
+
+    #[test]
+    fn escape_backslash() {
+        assert_eq!(parse_value(b"a\\\\nb"), b"a\\\\nb");
+        assert_eq!(parse_value(b"\"a\\\\b\""), b"a\\b");
+    }
+
+    #[test]
+    fn unquoted_windows_paths_are_kept() {
+        assert_eq!(parse_value(b"C:\\new\\temp"), b"C:\\new\\temp");
+        assert_eq!(parse_value(b"\\\\server\\share"), b"\\\\server\\share");
+        assert_eq!(parse_value(b"'C:\\new\\temp'"), b"C:\\new\\temp");
+    }
+
+    #[test]
+    fn unknown_escape_is_kept() {
+        assert_eq!(parse_value(b"C:\\Users"), b"C:\\Users");
+        assert_eq!(parse_value(b"\"a\\qb\""), b"a\\qb");
+    }
+
+    #[test]
+    fn trailing_lone_backslash_is_kept() {
+        assert_eq!(parse_value(b"abc\\"), b"abc\\");
+        assert_eq!(parse_value(b"\\"), b"\\");
+    }
+
+    #[test]
+    fn parse_int_prefixes() {
+        assert_eq!(parse_int("0xFF"), Some(255));
+        assert_eq!(parse_int("0Xff"), Some(255));
+        assert_eq!(parse_int("0b1010"), Some(10));
+        assert_eq!(parse_int("0B11"), Some(3));
+        assert_eq!(parse_int("0o17"), Some(15));
+        assert_eq!(parse_int("0O7"), Some(7));
+        assert_eq!(parse_int("42"), Some(42));
+        assert_eq!(parse_int("0"), Some(0));
+    }
+
+    #[test]
+    fn parse_int_signs_and_separators() {
+        assert_eq!(parse_int("0xFF_FF"), Some(0xFFFF));
+        assert_eq!(parse_int("1_000_000"), Some(1_000_000));
+        assert_eq!(parse_int("-0x10"), Some(-16));
+        assert_eq!(parse_int("+42"), Some(42));
+        assert_eq!(parse_int("-9223372036854775808"), Some(i64::MIN));
+    }
+
+    #[test]
+    fn parse_int_rejects_garbage() {
+        assert_eq!(parse_int("0xG1"), None);
+        assert_eq!(parse_int("0b102"), None);
+        assert_eq!(parse_int("0o8"), None);
+        assert_eq!(parse_int("0x"), None);
+        assert_eq!(parse_int("0x-1"), None);
+        assert_eq!(parse_int("12a"), None);
+        assert_eq!(parse_int(""), None);
+        assert_eq!(parse_int("0x 1"), None);
+        assert_eq!(parse_int("9223372036854775808"), None);
+    }
+
+    #[test]
+    fn whitespace_around_keys_and_values_is_stripped() {
+        let expected: Option<(&[u8], &[u8])> = Some((b"KEY", b"value"));
+        assert_eq!(split_line(b"KEY = value", b'='), expected);
+        assert_eq!(split_line(b"\tKEY\t=\tvalue\t", b'='), expected);
+        assert_eq!(split_line(b" \t KEY \t= \t value \t ", b'='), expected);
+        assert_eq!(split_line(b"  =value", b'='), None);
+    }
+
+    #[test]
+    fn parse_bool_yes_no() {
+        assert_eq!(parse_bool("yes"), Some(true));
+        assert_eq!(parse_bool("y"), Some(true));
+        assert_eq!(parse_bool("no"), Some(false));
+        assert_eq!(parse_bool("n"), Some(false));
+        // Mixed case is accepted, see parse_bool.
+        assert_eq!(parse_bool("Yes"), Some(true));
+        assert_eq!(parse_bool("N"), Some(false));
+    }
+
+    #[test]
+    fn parse_bool_is_case_insensitive() {
+        for value in ["TRUE", "True", "tRuE", "ON", "On", "YES", "yEs"] {
+            assert_eq!(parse_bool(value), Some(true), "{}", value);
+        }
+        for value in ["FALSE", "False", "fAlSe", "OFF", "Off", "NO", "nO"] {
+            assert_eq!(parse_bool(value), Some(false), "{}", value);
+        }
+        for value in ["", "maybe", "TRUEISH", "2", " true"] {
+            assert_eq!(parse_bool(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn split_line_uses_the_first_delimiter() {
+        assert_eq!(split_line(b"=value", b'='), None);
+        assert_eq!(split_line(b"  =1=2", b'='), None);
+        let expected: Option<(&[u8], &[u8])> = Some((b"A", b"1=2"));
+        assert_eq!(split_line(b"A=1=2", b'='), expected);
+    }
+}
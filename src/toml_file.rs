@@ -0,0 +1,100 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! TOML override file support.
+
+use crate::environment::Config;
+use crate::file::{open_file, Encoding};
+use std::io::{BufRead, Cursor, Error, ErrorKind, Read};
+use std::path::Path;
+use toml::{Table, Value};
+
+/// Returns the text of a scalar value, or None if the value is an array or a table.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(v) => Some(v.clone()),
+        Value::Integer(v) => Some(v.to_string()),
+        Value::Float(v) => Some(v.to_string()),
+        Value::Boolean(v) => Some(v.to_string()),
+        Value::Datetime(v) => Some(v.to_string()),
+        Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
+/// Writes an override entry with a double-quoted value.
+fn write_entry(name: &str, value: &str, delimiter: u8, out: &mut Vec<u8>) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(delimiter);
+    out.push(b'"');
+    for c in value.bytes() {
+        match c {
+            b'\\' => out.extend_from_slice(b"\\\\"),
+            b'"' => out.extend_from_slice(b"\\\""),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            v => out.push(v),
+        }
+    }
+    out.extend_from_slice(b"\"\n");
+}
+
+/// Flattens a table to override entries, joining nested keys with `_`.
+fn flatten(prefix: &str, table: &Table, delimiter: u8, out: &mut Vec<u8>) {
+    for (key, value) in table {
+        let mut name = key.to_ascii_uppercase();
+        if !prefix.is_empty() {
+            name = format!("{}_{}", prefix, name);
+        }
+        if name.bytes().any(|v| v == delimiter || v.is_ascii_whitespace()) {
+            continue;
+        }
+        match value {
+            Value::Table(v) => flatten(&name, v, delimiter, out),
+            Value::Array(v) => {
+                // Arrays of scalars are joined with commas, other arrays are skipped.
+                if let Some(items) = v.iter().map(scalar).collect::<Option<Vec<_>>>() {
+                    write_entry(&name, &items.join(","), delimiter, out);
+                }
+            }
+            v => write_entry(&name, &scalar(v).unwrap_or_default(), delimiter, out),
+        }
+    }
+}
+
+/// Opens a TOML file and flattens it to override entries.
+pub fn open(path: &Path, config: &Config) -> std::io::Result<Box<dyn BufRead>> {
+    let mut text = String::new();
+    open_file(path, config.max_file_size, Encoding::Utf8)?.read_to_string(&mut text)?;
+    let table: Table = text.parse().map_err(|e| {
+        warn!(?path, error = %e, "skipping invalid TOML override file");
+        Error::new(ErrorKind::InvalidData, e)
+    })?;
+    let mut out = Vec::new();
+    flatten("", &table, config.delimiter, &mut out);
+    Ok(Box::new(Cursor::new(out)))
+}
@@ -0,0 +1,125 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+use crate::file::{lines, open_file, os_str_from_bytes};
+use crate::parser;
+use crate::Environment;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// The reason of a [ParseWarning].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningReason {
+    /// The line does not contain the key/value delimiter and is ignored.
+    MissingDelimiter,
+
+    /// The key of the line is empty and the line is ignored.
+    EmptyKey,
+
+    /// The key or value is not encoded in a way supported on this platform and the line is
+    /// ignored.
+    InvalidEncoding,
+
+    /// The key was already defined earlier in the same file, only one of the definitions is used
+    /// depending on the [DuplicatePolicy](crate::DuplicatePolicy).
+    DuplicateKey,
+}
+
+impl Display for WarningReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WarningReason::MissingDelimiter => f.write_str("missing delimiter"),
+            WarningReason::EmptyKey => f.write_str("empty key"),
+            WarningReason::InvalidEncoding => f.write_str("invalid encoding"),
+            WarningReason::DuplicateKey => f.write_str("duplicate key"),
+        }
+    }
+}
+
+/// A warning about a line of an override file.
+#[derive(Debug, Clone)]
+pub struct ParseWarning {
+    /// The 1-based line number.
+    pub line: usize,
+
+    /// The reason of the warning.
+    pub reason: WarningReason,
+
+    /// The text of the offending line, with invalid UTF-8 sequences replaced.
+    pub text: String,
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}: {}", self.line, self.reason, self.text)
+    }
+}
+
+impl Environment {
+    /// Validates an override file using the settings of this environment.
+    ///
+    /// See [validate_path](crate::validate_path) for details.
+    pub fn validate_path<P: AsRef<Path>>(&self, path: P) -> std::io::Result<Vec<ParseWarning>> {
+        let path = path.as_ref();
+        let config = self.config();
+        let delimiter = config.delimiter;
+        let mut warnings = Vec::new();
+        let mut keys = HashSet::new();
+        let encoding = self.encoding_of(path);
+        for v in lines(open_file(path, config.max_file_size, encoding)?) {
+            let (line, data) = v?;
+            if parser::parse_include(&data).is_some() || parser::parse_unset(&data).is_some() {
+                continue;
+            }
+            let reason = match parser::split_line(&data, delimiter) {
+                None if parser::is_ignored(&data) => continue,
+                None if data.contains(&delimiter) => Some(WarningReason::EmptyKey),
+                None => Some(WarningReason::MissingDelimiter),
+                Some((key, value)) => {
+                    let value = parser::parse_value(value);
+                    if os_str_from_bytes(key).is_none() || os_str_from_bytes(&value).is_none() {
+                        Some(WarningReason::InvalidEncoding)
+                    } else if !keys.insert(key.to_vec()) {
+                        Some(WarningReason::DuplicateKey)
+                    } else {
+                        None
+                    }
+                }
+            };
+            if let Some(reason) = reason {
+                warnings.push(ParseWarning {
+                    line,
+                    reason,
+                    text: String::from_utf8_lossy(&data).into(),
+                });
+            }
+        }
+        Ok(warnings)
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c) 2022, BlockProject 3D
+//
+// All rights reserved.
+//
+// Redistribution and use in source and binary forms, with or without modification,
+// are permitted provided that the following conditions are met:
+//
+//     * Redistributions of source code must retain the above copyright notice,
+//       this list of conditions and the following disclaimer.
+//     * Redistributions in binary form must reproduce the above copyright notice,
+//       this list of conditions and the following disclaimer in the documentation
+//       and/or other materials provided with the distribution.
+//     * Neither the name of BlockProject 3D nor the names of its contributors
+//       may be used to endorse or promote products derived from this software
+//       without specific prior written permission.
+//
+// THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS
+// "AS IS" AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT
+// LIMITED TO, THE IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR
+// A PARTICULAR PURPOSE ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT OWNER OR
+// CONTRIBUTORS BE LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL,
+// EXEMPLARY, OR CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO,
+// PROCUREMENT OF SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR
+// PROFITS; OR BUSINESS INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF
+// LIABILITY, WHETHER IN CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING
+// NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE OF THIS
+// SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
+
+//! Hot-reload support for override files.
+
+use crate::Environment;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The time to wait after the last change before invalidating the cache.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// A handle to a running override file watcher.
+///
+/// Dropping the handle stops watching.
+pub struct WatchHandle {
+    watcher: Option<RecommendedWatcher>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        // Dropping the watcher drops the event sender which terminates the debounce thread.
+        drop(self.watcher.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn is_change(event: &notify::Result<Event>, files: &[PathBuf]) -> bool {
+    match event {
+        Ok(e) => {
+            (e.kind.is_create() || e.kind.is_modify() || e.kind.is_remove())
+                && e.paths.iter().any(|p| files.contains(p))
+        }
+        Err(_) => false,
+    }
+}
+
+fn run(env: &Environment, receiver: Receiver<notify::Result<Event>>, files: Vec<PathBuf>) {
+    while let Ok(event) = receiver.recv() {
+        if !is_change(&event, &files) {
+            continue;
+        }
+        // Wait for changes to settle so that a single save doesn't trigger a flurry of
+        // invalidations.
+        let mut deadline = Instant::now() + DEBOUNCE;
+        loop {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) => {
+                    if is_change(&event, &files) {
+                        deadline = Instant::now() + DEBOUNCE;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        env.clear_cache();
+    }
+}
+
+impl Environment {
+    /// Watches the override files of this environment and invalidates the cache whenever any of
+    /// them changes on disk.
+    ///
+    /// Only the override paths and the defaults file registered at the time of the call are
+    /// watched. Rapid successive changes are debounced into a single invalidation. Getters running
+    /// concurrently with an invalidation simply re-read the override files lazily.
+    ///
+    /// Dropping the returned [WatchHandle] stops watching.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the watcher could not be created or if a directory containing an
+    /// override file could not be watched.
+    pub fn watch(&'static self) -> notify::Result<WatchHandle> {
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for path in self.override_paths().into_iter().chain(self.defaults_path()) {
+            // Watch the parent directory as editors commonly replace files on save.
+            let dir = match path.parent() {
+                Some(v) if !v.as_os_str().is_empty() => v,
+                _ => Path::new("."),
+            };
+            let dir = dir.canonicalize().map_err(notify::Error::io)?;
+            if let Some(name) = path.file_name() {
+                files.push(dir.join(name));
+            }
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+        let (sender, receiver) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        for dir in &dirs {
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        let thread = std::thread::spawn(move || run(self, receiver, files));
+        Ok(WatchHandle {
+            watcher: Some(watcher),
+            thread: Some(thread),
+        })
+    }
+}